@@ -1,18 +1,31 @@
 use std::collections::HashMap;
+use std::env;
 use std::fs;
 use std::time::{Duration, Instant};
 use cpu_time::ProcessTime;
 use crossbeam_channel::{Receiver, Sender, unbounded};
 use bdd_sat_solver::{get_glucose_solution_no_malloc, GlucoseWrapper, parse_dimacs_and_add_clause_to_glucose, init_glucose_solver, run_glucose_parallel, print_incremental_stats};
-use bdd_sat_solver::expr::bool_expr::Expr;
 use bdd_sat_solver::parallel::clause_database::ClauseDatabase;
 use bdd_sat_solver::parser::parse::parse_dimacs;
 use bdd_sat_solver::statistics::stats::Stats;
+use bdd_sat_solver::variable_ordering::clause_schedule::clause_schedule;
 use bdd_sat_solver::variable_ordering::var_ordering::BddVarOrdering;
 
 #[tokio::main]
 async fn main() {
 
+    // the order to conjoin the clauses in, the file order by default
+    let args: Vec<String> = env::args().collect();
+    let schedule = args.get(1).map_or("file-order", |name| name.as_str()).to_string();
+    // over-approximate the Bdd once it grows past the node limit, down to the target size,
+    // by default once it grows past 10000 nodes, down to 5000 nodes
+    let limit = args.get(2).map_or(Some(10_000), |limit| limit.parse().ok());
+    let target = args.get(3).map_or(Some(5_000), |target| target.parse().ok());
+    let (Some(limit), Some(target), Some(_)) = (limit, target, clause_schedule(&schedule)) else {
+        println!("Usage: {} [clause schedule] [node limit] [target size]", args[0]);
+        return;
+    };
+
     let tests = fs::read_dir("./benchmarks/tests").unwrap();
     //let paths11 = fs::read_dir("./benchmarks/benchmarks2011").unwrap();
     //let paths12 = fs::read_dir("./benchmarks/benchmarks2012").unwrap();
@@ -36,7 +49,7 @@ async fn main() {
 
         let mut tasks = HashMap::new();
         for entry in path {
-            tasks.insert(1, tokio::spawn(tokio::time::timeout(Duration::from_secs(1000), glucose_with_bdd(entry.clone()))));
+            tasks.insert(1, tokio::spawn(tokio::time::timeout(Duration::from_secs(1000), glucose_with_bdd(entry.clone(), schedule.clone(), (limit, target)))));
             tasks.insert(0, tokio::spawn(tokio::time::timeout(Duration::from_secs(1000), glucose_without_bdd(entry.clone()))));
         }
 
@@ -56,7 +69,7 @@ async fn main() {
     }
 }
 
-async fn glucose_with_bdd(path: String) {
+async fn glucose_with_bdd(path: String, schedule: String, (limit, target): (usize, usize)) {
     let (sender1, receiver1): (Sender<()>, Receiver<()>) = unbounded();
     let (sender2, receiver2) = (sender1.clone(), receiver1.clone());
     let (sender3, receiver3) = (sender2.clone(), receiver2.clone());
@@ -72,14 +85,13 @@ async fn glucose_with_bdd(path: String) {
     // create the Dimacs instance
     let dimacs = parse_dimacs(&path);
 
-    // create the vector of the parsed expressions
-    let mut parsed_expr = Expr::parse_clauses(&dimacs.clauses);
-
     stats.parsing_time_bdd_world = started.elapsed();
     stats.parsing_time_bdd_cpu = start.try_elapsed().expect("Getting process time failed");
 
     // build the variable ordering
-    let var_ordering = BddVarOrdering::new(&dimacs);
+    let mut var_ordering = BddVarOrdering::new(&dimacs);
+    var_ordering.manager().write().unwrap().enable_approximation(limit, target);
+    let schedule = clause_schedule(&schedule).unwrap();
 
     // initiate the clause database
     let mut clause_database = ClauseDatabase::new();
@@ -100,7 +112,7 @@ async fn glucose_with_bdd(path: String) {
     let solver_wrapper = GlucoseWrapper::new(solver);
 
     let (ret, _bdd) = rayon::join(|| run_glucose_parallel(solver_wrapper, sender1,sender2, sender3, &mut stats_glucose),
-                                  || var_ordering.parallel_build(&mut parsed_expr, &mut clause_database, 0, solver_wrapper, &mut stats, receiver1,receiver2,receiver3));
+                                  || var_ordering.parallel_build(&dimacs.clauses, schedule.as_ref(), &mut clause_database, 0, solver_wrapper, &mut stats, receiver1,receiver2,receiver3));
 
     stats.solving_time_glucose_world = stats_glucose.solving_time_glucose_world;
     stats.solving_time_glucose_cpu = stats_glucose.solving_time_glucose_cpu;
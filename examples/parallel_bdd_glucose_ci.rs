@@ -3,25 +3,34 @@ use std::time::{Duration, Instant};
 use cpu_time::ProcessTime;
 use crossbeam_channel::{Receiver, Sender, unbounded};
 use bdd_sat_solver::{get_glucose_solution_no_malloc, GlucoseWrapper, parse_dimacs_and_add_clause_to_glucose, init_glucose_solver, run_glucose_parallel, print_incremental_stats};
-use bdd_sat_solver::expr::bool_expr::Expr;
+use bdd_sat_solver::approximation_strategy::approximation_strategy;
 use bdd_sat_solver::parallel::clause_database::ClauseDatabase;
 use bdd_sat_solver::parser::parse::parse_dimacs;
 use bdd_sat_solver::statistics::stats::Stats;
+use bdd_sat_solver::variable_ordering::clause_schedule::clause_schedule;
 use bdd_sat_solver::variable_ordering::var_ordering::BddVarOrdering;
 
 fn main() {
 
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        println!("Usage: {}", args[0]);
+        println!("Usage: {} <benchmark> [approximation strategy] [clause schedule] [node limit] [target size]", args[0]);
         return;
     }
     println!("Benchmark: {}", args[1]);
-    rayon::join(|| glucose_with_bdd(args[1].to_string()),
+    // the strategy to approximate the Bdd with, rounding up by default
+    let strategy = args.get(2).map_or("round-up", |name| name.as_str()).to_string();
+    // the order to conjoin the clauses in, the file order by default
+    let schedule = args.get(3).map_or("file-order", |name| name.as_str()).to_string();
+    // over-approximate the Bdd once it grows past the node limit, down to the target size,
+    // by default once it grows past 10000 nodes, down to 5000 nodes
+    let limit = args.get(4).map_or(10_000, |limit| limit.parse().expect("The node limit is not a number"));
+    let target = args.get(5).map_or(5_000, |target| target.parse().expect("The target size is not a number"));
+    rayon::join(|| glucose_with_bdd(args[1].to_string(), &strategy, &schedule, (limit, target)),
                 || glucose_without_bdd(args[1].to_string()));
 }
 
-fn glucose_with_bdd(path: String) {
+fn glucose_with_bdd(path: String, strategy: &str, schedule: &str, (limit, target): (usize, usize)) {
 
     let (sender1, receiver1): (Sender<()>, Receiver<()>) = unbounded();
     let (sender2, receiver2) = (sender1.clone(), receiver1.clone());
@@ -38,14 +47,15 @@ fn glucose_with_bdd(path: String) {
     // create the Dimacs instance
     let dimacs = parse_dimacs(&path);
 
-    // create the vector of the parsed expressions
-    let mut parsed_expr = Expr::parse_clauses(&dimacs.clauses);
-
     stats.parsing_time_bdd_world = started.elapsed();
     stats.parsing_time_bdd_cpu = start.try_elapsed().expect("Getting process time failed");
 
     // build the variable ordering
-    let var_ordering = BddVarOrdering::new(&dimacs);
+    let mut var_ordering = BddVarOrdering::new(&dimacs);
+    var_ordering.manager().write().unwrap().enable_approximation(limit, target);
+    let strategy = approximation_strategy(strategy).expect("Unknown approximation strategy");
+    var_ordering.manager().write().unwrap().set_approximation_strategy(strategy);
+    let schedule = clause_schedule(schedule).expect("Unknown clause schedule");
 
     // initiate the clause database
     let mut clause_database = ClauseDatabase::new();
@@ -66,7 +76,7 @@ fn glucose_with_bdd(path: String) {
     let solver_wrapper = GlucoseWrapper::new(solver);
 
     let (ret, _bdd) = rayon::join(|| run_glucose_parallel(solver_wrapper, sender1, sender2, sender3, &mut stats_glucose),
-                                  || var_ordering.parallel_build(&mut parsed_expr, &mut clause_database, 0, solver_wrapper, &mut stats, receiver1, receiver2, receiver3));
+                                  || var_ordering.parallel_build(&dimacs.clauses, schedule.as_ref(), &mut clause_database, 0, solver_wrapper, &mut stats, receiver1, receiver2, receiver3));
 
     stats.solving_time_glucose_world = stats_glucose.solving_time_glucose_world;
     stats.solving_time_glucose_cpu = stats_glucose.solving_time_glucose_cpu;
@@ -1,12 +1,192 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::time::Instant;
 use crossbeam_channel::{Receiver, TryRecvError};
 use crate::bdd::Bdd;
-use crate::bdd_util::{BddNode, BddPointer};
-use crate::statistics::stats::Stats;
+use crate::bdd_manager::DetachedBdd;
+use crate::bdd_util::{BddNode, BddPointer, BddVar};
+use crate::statistics::stats::{ApproximationStep, Stats};
+use crate::variable_ordering::var_ordering::BddVarOrdering;
 
 impl Bdd {
 
+    /// Over-approximate the Bdd by rounding up its smallest off-set. The nodes
+    /// are rewritten on a detached copy, which is then imported back into the
+    /// manager, so the other `Bdd`s sharing these nodes stay untouched.
+    pub fn round_up(&mut self, stats: &mut Stats) {
+        let now = Instant::now();
+        if !self.is_true() && !self.is_false() {
+            let manager = self.manager().clone();
+            // the sizes are counted on the manager: the detached copy has no complement
+            // edges, so a node used in both polarities is in it twice
+            let (mut detached, size_before) = {
+                let guard = manager.read().unwrap();
+                (guard.detach(self.root_pointer()), guard.node_count(self.root_pointer()))
+            };
+            let redirected = detached.round_up();
+            let mut guard = manager.write().unwrap();
+            let root = guard.import(&detached);
+            *self = Bdd::from_locked(&manager, &guard, root);
+            if let Some((var, high)) = redirected {
+                let size_after = guard.node_count(root);
+                stats.add_approximation_step(ApproximationStep { redirected: vec![(var.0, high)], over: true, size_before, size_after });
+            }
+        }
+        stats.add_t_approx(now.elapsed());
+    }
+
+    /// Round up the Bdd until it has at most `target` nodes, see `DetachedBdd::round_up_to`.
+    /// The rounded up nodes are all redirected in one pass over a detached copy.
+    /// Does nothing if the other thread has finished.
+    pub fn approximate_to(&mut self, target: usize, stats: &mut Stats, receiver: Receiver<()>) {
+        if self.size() <= target || self.is_true() || self.is_false() {
+            return;
+        }
+        match receiver.try_recv() {
+            Ok(_) | Err(TryRecvError::Disconnected) => return,
+            Err(TryRecvError::Empty) => {}
+        }
+        let now = Instant::now();
+        let manager = self.manager().clone();
+        let (detached, size_before) = {
+            let guard = manager.read().unwrap();
+            (guard.detach(self.root_pointer()), guard.node_count(self.root_pointer()))
+        };
+        let redirects = detached.round_up_to(target);
+        let rounded = detached.redirect(&redirects).expect("Rounding up never leads to the false formula");
+        let mut guard = manager.write().unwrap();
+        let root = guard.import(&rounded);
+        *self = Bdd::from_locked(&manager, &guard, root);
+        let size_after = guard.node_count(root);
+        let redirected = redirects.iter().map(|(ptr, high, _)| (detached.var_of_ptr(*ptr).0, *high)).collect();
+        stats.add_approximation_step(ApproximationStep { redirected, over: true, size_before, size_after });
+        stats.add_t_approx(now.elapsed());
+    }
+
+    /// Approximate the Bdd down to the target size of its manager with the
+    /// strategy of the manager, if approximation is enabled and the Bdd of
+    /// `size` nodes has grown past the node limit. The `ordering` is the one
+    /// of the Bdd. Returns true if the Bdd was approximated.
+    pub fn approximate_if_needed(&mut self, size: usize, ordering: &BddVarOrdering, stats: &mut Stats, receiver: Receiver<()>) -> bool {
+        let (target, strategy) = {
+            let manager = self.manager().read().unwrap();
+            (manager.approximation_due(size), manager.approximation_strategy())
+        };
+        if let Some(target) = target {
+            strategy.approximate(self, ordering, target, stats, receiver);
+        }
+        target.is_some()
+    }
+
+    /// Under-approximate the Bdd by rounding down the node with a direct one edge
+    /// that is reached by the fewest assignments. The counterpart of `round_up`:
+    /// every model of the result is a model of the Bdd. A Bdd whose models all
+    /// go through a single one edge is left as it is, instead of becoming false.
+    pub fn round_down(&mut self, stats: &mut Stats) {
+        self.redirect_edges(false, stats, |detached| detached.round_down().into_iter().collect());
+    }
+
+    /// Under-approximate the Bdd by heavy-branch subsetting, until it has at most
+    /// `threshold` nodes. From the root down, the child with fewer models is
+    /// redirected to zero and the walk goes on in the child with more.
+    pub fn heavy_branch_subset(&mut self, threshold: usize, stats: &mut Stats) {
+        self.redirect_edges(false, stats, |detached| detached.heavy_branch_subset(threshold));
+    }
+
+    /// Under-approximate the Bdd by short-path subsetting: only the nodes on the
+    /// shortest paths to one are kept, as many as fit into `threshold` nodes.
+    /// A short path fixes few variables and so covers many models. If not even
+    /// all the shortest paths fit, a single one of them is kept.
+    pub fn short_path_subset(&mut self, threshold: usize, stats: &mut Stats) {
+        self.redirect_edges(false, stats, |detached| detached.short_path_subset(threshold));
+    }
+
+    /// Redirect edges to zero on a detached copy of the Bdd and import the result
+    /// back into the manager, which under-approximates the Bdd. If `over`, the copy
+    /// is of the negation instead, so the Bdd is over-approximated. `redirect`
+    /// returns the redirected edges. A Bdd that would become a terminal is kept.
+    pub(crate) fn redirect_edges<F>(&mut self, over: bool, stats: &mut Stats, redirect: F)
+        where F: FnOnce(&mut DetachedBdd) -> Vec<(BddVar, bool)> {
+        let now = Instant::now();
+        if !self.is_true() && !self.is_false() {
+            let manager = self.manager().clone();
+            let (mut detached, size_before) = {
+                let guard = manager.read().unwrap();
+                (guard.detach(self.root_pointer().complement_if(over)), guard.node_count(self.root_pointer()))
+            };
+            let redirected = redirect(&mut detached);
+            // the edges are redirected already, the rebuild only reduces the diagram
+            if let Some(detached) = detached.redirect(&[]) {
+                let mut guard = manager.write().unwrap();
+                let root = guard.import(&detached);
+                *self = Bdd::from_locked(&manager, &guard, root.complement_if(over));
+                if !redirected.is_empty() {
+                    let size_after = guard.node_count(root);
+                    let redirected = redirected.into_iter().map(|(var, high)| (var.0, high)).collect();
+                    stats.add_approximation_step(ApproximationStep { redirected, over, size_before, size_after });
+                }
+            }
+        }
+        stats.add_t_approx(now.elapsed());
+    }
+
+    /// Check that the nodes of the Bdd form a reduced ordered diagram: every
+    /// pointer is in the arena, the variables follow the ordering, no node has
+    /// two equal children or a complemented high edge, and no two nodes are
+    /// equal. Returns the first violation found.
+    pub fn check_invariants(&self, ordering: &BddVarOrdering) -> Result<(), String> {
+        let manager = self.manager().read().unwrap();
+        let mut visited: HashSet<usize> = HashSet::new();
+        let mut unique: HashMap<(BddVar, BddPointer, BddPointer), usize> = HashMap::new();
+        let mut stack = vec![self.root_pointer()];
+        while let Some(ptr) = stack.pop() {
+            let idx = ptr.to_index();
+            if idx >= manager.size() {
+                return Err(format!("The pointer {:?} is outside of the arena of {} nodes", ptr, manager.size()));
+            }
+            if ptr.is_terminal() || !visited.insert(idx) {
+                continue;
+            }
+            // the edges as stored, without the complement tag of the pointer
+            let (var, low, high) = (manager.var_of_ptr(ptr), manager.low_node_ptr(ptr.regular()), manager.high_node_ptr(ptr.regular()));
+            let Some(level) = ordering.0.get(&var.0) else {
+                return Err(format!("The variable of the node {} is not in the ordering", idx));
+            };
+            if low == high {
+                return Err(format!("The node {} is redundant, both of its edges point to {:?}", idx, low));
+            }
+            if high.is_complemented() || high.is_zero() {
+                return Err(format!("The node {} has a complemented high edge", idx));
+            }
+            if let Some(duplicate) = unique.insert((var, low, high), idx) {
+                return Err(format!("The nodes {} and {} are equal", duplicate, idx));
+            }
+            for child in [low, high] {
+                if child.to_index() >= manager.size() {
+                    return Err(format!("The pointer {:?} is outside of the arena of {} nodes", child, manager.size()));
+                }
+                let child_level = ordering.0.get(&manager.var_of_ptr(child).0);
+                if !child.is_terminal() && child_level.is_some_and(|child_level| child_level <= level) {
+                    return Err(format!("The child {} of the node {} is not below it in the ordering", child.to_index(), idx));
+                }
+                stack.push(child);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BddVarOrdering {
+
+    /// True if `approx` is an over-approximation of `original`, that is if every
+    /// model of the original is a model of the approximation.
+    pub fn verify_over_approximation(&self, original: &Bdd, approx: &Bdd) -> bool {
+        self.imp(original, approx).is_true()
+    }
+}
+
+impl DetachedBdd {
+
     /// Find the node with the smallest off-set for the
     /// approximation algorithm rounding-up. An off-set
     /// is a set of the paths leading to 0.
@@ -14,9 +194,6 @@ impl Bdd {
         if self.is_true() {
             return None;
         }
-        if self.is_false() {
-            return Some(self.root_pointer());
-        }
         // We are searching for the off-set
         let zero = BddPointer::new_zero();
         let one = BddPointer::new_one();
@@ -39,7 +216,8 @@ impl Bdd {
             }
         }
         let mut off_set_vec: Vec<_> = off_set.into_iter().collect();
-        off_set_vec.sort_by(|a, b| b.1.cmp(&a.1));
+        // ties are broken by the index, so the choice does not depend on the hashing
+        off_set_vec.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.to_index().cmp(&b.0.to_index())));
         /*
         for (ptr,_) in off_set {
             if self.var_of_ptr(ptr).0 > var_acc.0 {
@@ -51,183 +229,348 @@ impl Bdd {
         Some(off_set_vec[0].0)
     }
 
-    pub fn round_up(&mut self, stats: &mut Stats, receiver: Receiver<()>) {
-        let now = Instant::now();
-        if let Some(ptr) = self.off_set() {
-            if self.low_node_ptr(ptr).is_zero() {
-                // set the low node of the pointer to true
-                self.replace_low(ptr, BddPointer::new_one());
-            } else {
-                self.replace_high(ptr, BddPointer::new_one());
-            }
-
-            let mut parents = self.update(ptr, BddPointer::new_one());
+    /// Redirect the zero edge of the node with the smallest off-set to one on a
+    /// detached diagram. Returns the variable of the node and true if its high
+    /// edge was redirected, or nothing if the diagram is the true formula.
+    pub fn round_up(&mut self) -> Option<(BddVar, bool)> {
+        let ptr = self.off_set()?;
+        let high = !self.low_node_ptr(ptr).is_zero();
+        let var = self.var_of_ptr(ptr);
+        // the node becomes redundant, and so do its parents whose other edge is one
+        *self = self.redirect(&[(ptr, high, BddPointer::new_one())])
+            .expect("Rounding up never leads to the false formula");
+        Some((var, high))
+    }
 
-            while let Some(idx) = parents.pop() {
-                match receiver.try_recv() {
-                    Ok(_) | Err(TryRecvError::Disconnected) => {
-                        println!("Terminating the approximation.");
-                        println!(" ");
-                        break;
+    /// The zero edges that `round_up` redirects to one step by step, until the
+    /// detached diagram has at most `target` nodes, collected for one `redirect`.
+    /// A rounded up node becomes one, and so do its parents whose other edge is
+    /// one, while its parents whose other edge is zero become the next candidates.
+    /// The size only counts the nodes that became one, so the rebuilt diagram,
+    /// where equal nodes are merged as well, is never bigger.
+    pub fn round_up_to(&self, target: usize) -> Vec<(BddPointer, bool, BddPointer)> {
+        let size = self.size();
+        let (zero, one) = (BddPointer::new_zero(), BddPointer::new_one());
+        let mut edges: Vec<(BddPointer, BddPointer)> = vec![(zero, zero); size];
+        let mut parents: Vec<Vec<usize>> = vec![Vec::new(); size];
+        for ptr in (2..size).map(BddPointer::new) {
+            let (low, high) = (self.low_node_ptr(ptr), self.high_node_ptr(ptr));
+            edges[ptr.to_index()] = (low, high);
+            parents[low.to_index()].push(ptr.to_index());
+            parents[high.to_index()].push(ptr.to_index());
+        }
+        // the nodes with a zero and a one edge, the one with the smallest index first like in `off_set`
+        let is_literal = |edges: &(BddPointer, BddPointer)| {
+            (edges.0.is_zero() && edges.1.is_one()) || (edges.0.is_one() && edges.1.is_zero())
+        };
+        let mut candidates: BinaryHeap<Reverse<usize>> = (2..size)
+            .filter(|idx| is_literal(&edges[*idx]))
+            .map(Reverse)
+            .collect();
+
+        let mut rounded = vec![false; size];
+        let mut estimate = size;
+        let mut redirects = Vec::new();
+        while estimate > target {
+            let idx = match candidates.pop() {
+                Some(Reverse(idx)) => idx,
+                None => break,
+            };
+            if rounded[idx] {
+                continue;
+            }
+            let high = !edges[idx].0.is_zero();
+            redirects.push((BddPointer::new(idx), high, one));
+            let mut stack = vec![idx];
+            while let Some(node) = stack.pop() {
+                rounded[node] = true;
+                estimate -= 1;
+                for parent in &parents[node] {
+                    if rounded[*parent] {
+                        continue;
+                    }
+                    let edge = &mut edges[*parent];
+                    if edge.0 == BddPointer::new(node) {
+                        edge.0 = one;
+                    }
+                    if edge.1 == BddPointer::new(node) {
+                        edge.1 = one;
+                    }
+                    if edge.0.is_one() && edge.1.is_one() {
+                        stack.push(*parent);
+                    } else if is_literal(edge) {
+                        candidates.push(Reverse(*parent));
                     }
-                    Err(TryRecvError::Empty) => {}
                 }
-                parents.extend(self.update(BddPointer::new(idx), BddPointer::new_one()));
             }
         }
-        stats.add_t_approx(now.elapsed());
+        redirects
     }
 
-    pub fn update(&mut self, pointer: BddPointer, replace: BddPointer) -> Vec<usize> {
-        // the pointer is a terminal one pointer now
-        // and needs to be removed after all its parents redirected to one
-        self.0.remove(pointer.to_index());
-
-        let mut existing: HashMap<BddNode, BddPointer> = HashMap::new();
-        let mut to_replace: Vec<(usize,BddPointer)> = Vec::new();
-
-        // find the parent nodes and update them
-        let mut parents = Vec::new();
-
-        for (idx, node) in self.0.iter_mut().enumerate() {
-            // The low pointer of the node will be replaced with one
-            if node.low == pointer {
-                node.replace_low(replace);
-                // If this update makes the node pointer the one pointer
-                // it goes to the list of parents for the recursive procedure
-                if node.high.is_one() {
-                    parents.push(idx);
-                }
-            }
-            if node.high == pointer {
-                node.replace_high(replace);
-                if node.low.is_one() {
-                    parents.push(idx);
-                }
-            }
-            //each node that points to a pointer with an index higher
-            // than the one which was deleted, it should be decreased by one
-            if node.low.to_index() > pointer.to_index() {
-                node.decrease_low();
-            }
-            if node.high.to_index() > pointer.to_index() {
-                node.decrease_high();
-            }
-            if node.low == node.high && !node.is_terminal() {
-                // if the high and low pointer of a node are the same delete
-                // this node too. Of-course the node shouldn't be a terminal node
-                to_replace.push((idx, node.low));
-            }
-            // if the node as its formed already exists, it should be replaced
-            if let Some(i) = existing.get(node) {
-                if !to_replace.contains(&(idx, *i)) {
-                    to_replace.push((idx, *i));
-                }
-            }
-            existing.insert(*node, BddPointer::new(idx));
+    /// Rebuild a detached diagram in a single bottom-up pass, with every edge
+    /// `(node, high)` of `redirects` pointing at its new target below the node.
+    /// Nodes whose two edges now point to the same child are dropped, equal nodes
+    /// are merged through a fresh unique table, and the nodes no longer reachable
+    /// from the root are left behind. So the time is linear in the size of the
+    /// diagram. Returns nothing if the diagram became the false formula.
+    pub fn redirect(&self, redirects: &[(BddPointer, bool, BddPointer)]) -> Option<DetachedBdd> {
+        let size = self.size();
+        let zero = BddPointer::new_zero();
+        let mut edges: Vec<(BddPointer, BddPointer)> = vec![(zero, zero); size];
+        for ptr in (2..size).map(BddPointer::new) {
+            edges[ptr.to_index()] = (self.low_node_ptr(ptr), self.high_node_ptr(ptr));
+        }
+        for (ptr, high, target) in redirects {
+            let edge = &mut edges[ptr.to_index()];
+            if *high { edge.1 = *target } else { edge.0 = *target }
         }
 
-        // make the deletes
-        self.delete(to_replace);
+        // the nodes reachable from the root over the redirected edges
+        let mut live = vec![false; size];
+        live[self.root_pointer().to_index()] = true;
+        for idx in (2..size).rev() {
+            if live[idx] {
+                live[edges[idx].0.to_index()] = true;
+                live[edges[idx].1.to_index()] = true;
+            }
+        }
 
-        parents
+        let mut rebuilt = DetachedBdd::new();
+        let mut unique: HashMap<BddNode, BddPointer> = HashMap::new();
+        let mut renaming: Vec<BddPointer> = vec![zero; size];
+        renaming[1] = BddPointer::new_one();
+        for idx in (2..size).filter(|idx| live[*idx]) {
+            let low = renaming[edges[idx].0.to_index()];
+            let high = renaming[edges[idx].1.to_index()];
+            renaming[idx] = if low == high {
+                low
+            } else {
+                let node = BddNode::mk_node(self.var_of_ptr(BddPointer::new(idx)), low, high);
+                *unique.entry(node).or_insert_with(|| {
+                    rebuilt.push_node(node);
+                    BddPointer::new(rebuilt.size() - 1)
+                })
+            };
+        }
+        let root = renaming[self.root_pointer().to_index()];
+        // the nodes below the root come first, so the root is the last node
+        debug_assert!(root.is_terminal() || root.to_index() == rebuilt.size() - 1);
+        if root.is_zero() { None } else { Some(rebuilt) }
     }
 
-    pub fn delete(&mut self, mut to_replace: Vec<(usize, BddPointer)>) {
-        while let Some((r,n)) = to_replace.pop() {
-            self.0.remove(r);
+    /// The share of the assignments satisfying each node of a detached diagram.
+    pub(crate) fn densities(&self) -> Vec<f64> {
+        let mut densities = vec![0.0, 1.0];
+        for ptr in (2..self.size()).map(BddPointer::new) {
+            let (low, high) = (self.low_node_ptr(ptr), self.high_node_ptr(ptr));
+            densities.push((densities[low.to_index()] + densities[high.to_index()]) / 2.0);
+        }
+        densities
+    }
 
-            let mut existing: HashMap<BddNode, BddPointer> = HashMap::new();
+    /// The share of the assignments reaching each node of a detached diagram from its root.
+    pub(crate) fn reach_probabilities(&self) -> Vec<f64> {
+        let mut reach = vec![0.0; self.size()];
+        reach[self.root_pointer().to_index()] = 1.0;
+        for ptr in (2..self.size()).rev().map(BddPointer::new) {
+            let share = reach[ptr.to_index()] / 2.0;
+            reach[self.low_node_ptr(ptr).to_index()] += share;
+            reach[self.high_node_ptr(ptr).to_index()] += share;
+        }
+        reach
+    }
 
-            // replace the nodes whose low or high pointers point at the node pointer
-            // with the new pointer
-            let ptr = BddPointer::new(r);
-            for (idx, node) in self.0.iter_mut().enumerate() {
-                if node.low == ptr {
-                    node.replace_low(n);
+    /// Redirect the one edge of a detached diagram that the fewest assignments
+    /// go through to zero. Returns the variable of the node and true if its high
+    /// edge was redirected, or nothing if the diagram is a terminal. The node
+    /// is left with two zero edges, which the import into a manager reduces.
+    pub fn round_down(&mut self) -> Option<(BddVar, bool)> {
+        let reach = self.reach_probabilities();
+        let (ptr, high) = (2..self.size()).map(BddPointer::new)
+            .filter_map(|ptr| {
+                if self.high_node_ptr(ptr).is_one() {
+                    Some((ptr, true))
+                } else if self.low_node_ptr(ptr).is_one() {
+                    Some((ptr, false))
+                } else {
+                    None
                 }
-                if node.high == ptr {
-                    node.replace_high(n);
+            })
+            .min_by(|a, b| reach[a.0.to_index()].total_cmp(&reach[b.0.to_index()]))?;
+        if high {
+            self.replace_high(ptr, BddPointer::new_zero());
+        } else {
+            self.replace_low(ptr, BddPointer::new_zero());
+        }
+        Some((self.var_of_ptr(ptr), high))
+    }
+
+    /// Heavy-branch subsetting of a detached diagram down to `threshold` nodes.
+    /// Returns the redirected edges, see `round_down`.
+    pub fn heavy_branch_subset(&mut self, threshold: usize) -> Vec<(BddVar, bool)> {
+        let densities = self.densities();
+        let size = self.size();
+        let root = self.root_pointer();
+        // the number of edges into every node reachable from the root, counted once
+        // top-down, so a redirect only has to visit the nodes it frees
+        let mut references = vec![0; size];
+        references[root.to_index()] = 1;
+        for ptr in (2..size).rev().map(BddPointer::new) {
+            if references[ptr.to_index()] > 0 {
+                references[self.low_node_ptr(ptr).to_index()] += 1;
+                references[self.high_node_ptr(ptr).to_index()] += 1;
+            }
+        }
+        let mut count = 2 + (2..size).filter(|idx| references[*idx] > 0).count();
+        let mut redirected = Vec::new();
+        let mut ptr = root;
+        while !ptr.is_terminal() && count > threshold {
+            let (low, high) = (self.low_node_ptr(ptr), self.high_node_ptr(ptr));
+            // on a tie the low child is kept
+            let high_is_light = densities[high.to_index()] <= densities[low.to_index()];
+            let (light, heavy) = if high_is_light { (high, low) } else { (low, high) };
+            if !light.is_zero() {
+                if high_is_light {
+                    self.replace_high(ptr, BddPointer::new_zero());
+                } else {
+                    self.replace_low(ptr, BddPointer::new_zero());
                 }
-                if node.low.to_index() > r {
-                    node.decrease_low();
+                redirected.push((self.var_of_ptr(ptr), high_is_light));
+                // the nodes only reachable through the light child are gone
+                let mut stack = vec![light];
+                while let Some(freed) = stack.pop() {
+                    if freed.is_terminal() {
+                        continue;
+                    }
+                    references[freed.to_index()] -= 1;
+                    if references[freed.to_index()] == 0 {
+                        count -= 1;
+                        stack.push(self.low_node_ptr(freed));
+                        stack.push(self.high_node_ptr(freed));
+                    }
                 }
-                if node.high.to_index() > r {
-                    node.decrease_high();
+            }
+            ptr = heavy;
+        }
+        redirected
+    }
+
+    /// Short-path subsetting of a detached diagram down to `threshold` nodes.
+    /// Returns the redirected edges, see `round_down`.
+    pub fn short_path_subset(&mut self, threshold: usize) -> Vec<(BddVar, bool)> {
+        let size = self.size();
+        // the shortest paths from every node down to one and from the root down to every node
+        if self.is_true() {
+            return Vec::new();
+        }
+        let mut to_one = vec![usize::MAX, 0];
+        for ptr in (2..size).map(BddPointer::new) {
+            let shortest = to_one[self.low_node_ptr(ptr).to_index()].min(to_one[self.high_node_ptr(ptr).to_index()]);
+            to_one.push(shortest.saturating_add(1));
+        }
+        let mut from_root = vec![usize::MAX; size];
+        from_root[size - 1] = 0;
+        for ptr in (2..size).rev().map(BddPointer::new) {
+            for child in [self.low_node_ptr(ptr), self.high_node_ptr(ptr)] {
+                from_root[child.to_index()] = from_root[child.to_index()].min(from_root[ptr.to_index()] + 1);
+            }
+        }
+        let length = |idx: usize| from_root[idx].saturating_add(to_one[idx]);
+
+        // the longest path length such that the nodes on the paths up to this length fit
+        let mut lengths: Vec<usize> = (2..size).map(length).collect();
+        lengths.sort_unstable();
+        let budget = threshold.saturating_sub(2);
+        let Some(max_length) = (0..lengths.len().min(budget)).rev()
+            .find(|i| lengths.get(i + 1) != Some(&lengths[*i]))
+            .map(|i| lengths[i]) else {
+            return self.shortest_path_subset(&to_one);
+        };
+
+        let mut redirected = Vec::new();
+        for ptr in (2..size).map(BddPointer::new).filter(|ptr| length(ptr.to_index()) <= max_length) {
+            for (child, high) in [(self.low_node_ptr(ptr), false), (self.high_node_ptr(ptr), true)] {
+                if child.is_terminal() || from_root[ptr.to_index()] + 1 + to_one[child.to_index()] <= max_length {
+                    continue;
                 }
-                if node.low == node.high && !node.is_terminal() {
-                    // if the high and low pointer of a node are the same delete
-                    // this node too. Ofcourse the node shouldn't be a terminal node
-                    to_replace.push((idx, node.low));
+                if high {
+                    self.replace_high(ptr, BddPointer::new_zero());
+                } else {
+                    self.replace_low(ptr, BddPointer::new_zero());
                 }
-                // if the node as its formed already exists, it should be replaced
-                if let Some(i) = existing.get(node) {
-                    if !to_replace.contains(&(idx, *i)) {
-                        to_replace.push((idx, *i));
-                    }
+                redirected.push((self.var_of_ptr(ptr), high));
+            }
+        }
+        redirected
+    }
+
+    /// Keep a single shortest path of a detached diagram, given the length of
+    /// the shortest path from every node down to one.
+    fn shortest_path_subset(&mut self, to_one: &[usize]) -> Vec<(BddVar, bool)> {
+        let mut redirected = Vec::new();
+        let mut ptr = self.root_pointer();
+        while !ptr.is_terminal() {
+            let (low, high) = (self.low_node_ptr(ptr), self.high_node_ptr(ptr));
+            // on a tie the low child is kept
+            let high_is_longer = to_one[high.to_index()] >= to_one[low.to_index()];
+            let (longer, shorter) = if high_is_longer { (high, low) } else { (low, high) };
+            if !longer.is_zero() {
+                if high_is_longer {
+                    self.replace_high(ptr, BddPointer::new_zero());
+                } else {
+                    self.replace_low(ptr, BddPointer::new_zero());
                 }
-                existing.insert(*node, BddPointer::new(idx));
+                redirected.push((self.var_of_ptr(ptr), high_is_longer));
             }
+            ptr = shorter;
         }
+        redirected
     }
 
+    /// Skip the nodes whose variable is already decided on every path to them,
+    /// which only happens in a diagram that is not ordered: the edge into such a
+    /// node is redirected to the child that the decision leads to. The redirects
+    /// are applied in one `redirect` pass.
     pub fn tauto_reduction(&mut self) {
-        if self.is_true() || self.is_false() {
+        if self.is_true() {
             return;
         }
-        let last_child = self.root_pointer();
-
-        let path = Vec::new();
-        self.tauto_reduction_rec(path,last_child);
-    }
-
-    pub fn tauto_reduction_rec(&mut self, path: Vec<(BddPointer,bool)>, mut last_child: BddPointer) {
-
-        if !self.low_node_ptr(last_child).is_terminal() {
-            let mut pathlow = Vec::new();
-            pathlow.extend(path.clone());
-
-            // add the variable of the top level and its evaluation
-            // to the path so that if the variable is encountered again
-            // the node will be marked as redundant.
-            pathlow.push((last_child, false)); // false as we are in the low node
-
-            let curr_low = self.low_node_ptr(last_child);
-            // now begin to search for redundant nodes
-
-            if pathlow.iter().any(|tuple| self.var_of_ptr(tuple.0)
-                == self.var_of_ptr(curr_low) && tuple.1 == false) {
-                // connect the low of the last child with the low of the current
-                // node which is redundant and will be removed.
-                self.replace_low(last_child, self.low_node_ptr(curr_low));
-                self.delete_node(curr_low, pathlow);
-                last_child = BddPointer::new(last_child.0 as usize -1);
-            } else {
-                self.tauto_reduction_rec(pathlow, curr_low);
+        let size = self.size();
+        // the variables decided on every path from the root to each node
+        let mut decided: Vec<Option<HashMap<BddVar, bool>>> = vec![None; size];
+        decided[self.root_pointer().to_index()] = Some(HashMap::new());
+        let mut redirects = Vec::new();
+        for ptr in (2..size).rev().map(BddPointer::new) {
+            let on_paths = match decided[ptr.to_index()].take() {
+                Some(on_paths) => on_paths,
+                None => continue,
+            };
+            for high in [false, true] {
+                let mut on_edge = on_paths.clone();
+                on_edge.insert(self.var_of_ptr(ptr), high);
+                let child = if high { self.high_node_ptr(ptr) } else { self.low_node_ptr(ptr) };
+                let mut target = child;
+                while !target.is_terminal() {
+                    match on_edge.get(&self.var_of_ptr(target)) {
+                        Some(true) => target = self.high_node_ptr(target),
+                        Some(false) => target = self.low_node_ptr(target),
+                        None => break,
+                    }
+                }
+                if target != child {
+                    redirects.push((ptr, high, target));
+                }
+                if !target.is_terminal() {
+                    let entry = &mut decided[target.to_index()];
+                    *entry = Some(match entry.take() {
+                        Some(on_paths) => on_paths.into_iter().filter(|(var, value)| on_edge.get(var) == Some(value)).collect(),
+                        None => on_edge,
+                    });
+                }
             }
         }
-        if !self.high_node_ptr(last_child).is_terminal() {
-
-            let mut pathhigh = Vec::new();
-            pathhigh.extend(path);
-
-            // add the variable of the top level and its evaluation
-            // to the path so that if the variable is encountered again
-            // the node will be marked as redundant.
-            pathhigh.push((last_child, true)); // true as we are in the high node
-
-            let curr_high = self.high_node_ptr(last_child);
-            // now begin to search for redundant nodes
-            if pathhigh.iter().any(|tuple| self.var_of_ptr(tuple.0)
-                == self.var_of_ptr(curr_high) && tuple.1 == true) {
-                // connect the high of the last child with the high of the current
-                // node which is redundant and will be removed.
-                self.replace_high(last_child, self.high_node_ptr(curr_high));
-                self.delete_node(curr_high, pathhigh);
-                //last_child = BddPointer::new(last_child.0 as usize -1);
-            } else {
-                self.tauto_reduction_rec(pathhigh, curr_high);
-            }
+        if let Some(reduced) = self.redirect(&redirects) {
+            *self = reduced;
         }
     }
 }
@@ -235,9 +578,177 @@ impl Bdd {
 
 #[cfg(test)]
 mod tests {
+    use crossbeam_channel::unbounded;
     use crate::bdd::Bdd;
+    use crate::bdd_manager::DetachedBdd;
     use crate::bdd_util::{BddNode, BddPointer, BddVar};
+    use crate::expr::bool_expr::Expr;
+    use crate::parser::parse::parse_dimacs;
+    use crate::statistics::stats::Stats;
+    use crate::variable_ordering::clause_schedule::FileOrder;
+    use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+
+    #[test]
+    fn approximate_down_to_the_budget() {
+        let dimacs = parse_dimacs("tests/test4.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let original = var_ordering.build_clauses(&dimacs.clauses, &FileOrder);
+        let size = original.size();
+        let (_sender, receiver) = unbounded();
+        let mut stats = Stats::new();
+
+        var_ordering.manager().write().unwrap().enable_approximation(size, size / 2);
+        let mut bdd = original.clone();
+        assert!(!bdd.approximate_if_needed(size, &var_ordering, &mut stats, receiver.clone()));
+        assert_eq!(bdd, original);
+        assert!(stats.approximation_steps().is_empty());
+
+        var_ordering.manager().write().unwrap().enable_approximation(size - 1, size / 2);
+        assert!(bdd.approximate_if_needed(size, &var_ordering, &mut stats, receiver));
+        assert!(bdd.size() <= size / 2);
+        // every model of the original is still a model
+        assert!(var_ordering.and(&original, &bdd.negate()).is_false());
+        // the nodes are rounded up in a single pass
+        let steps = stats.approximation_steps();
+        assert_eq!(steps.len(), 1);
+        assert!(!steps[0].redirected.is_empty());
+        assert_eq!(steps[0].size_before, size);
+        assert_eq!(steps[0].size_after, bdd.size());
+    }
+
+    #[test]
+    fn sizes_of_both_polarities() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        // x1 xor (x2 xor x3) uses the node of x3 in both polarities, and so the node of
+        // (x2 xor x3), so the detached copy of the five nodes has two more nodes
+        let (x1, x2, x3) = (var_ordering.build(&mut Expr::Var(1)), var_ordering.build(&mut Expr::Var(2)), var_ordering.build(&mut Expr::Var(3)));
+        let original = var_ordering.xor(&x1, &var_ordering.xor(&x2, &x3));
+        assert_eq!(original.size(), 5);
+        assert!(var_ordering.manager().read().unwrap().detach(original.root_pointer()).size() > original.size());
+        let mut stats = Stats::new();
+
+        let mut rounded_up = original.clone();
+        rounded_up.round_up(&mut stats);
+        let mut rounded_down = original.clone();
+        rounded_down.round_down(&mut stats);
+        let steps = stats.approximation_steps();
+        assert_eq!(steps.len(), 2);
+        assert!(steps.iter().all(|step| step.size_before == original.size()));
+        assert_eq!(steps[0].size_after, rounded_up.size());
+        assert_eq!(steps[1].size_after, rounded_down.size());
+    }
+
+    #[test]
+    fn under_approximations() {
+        let dimacs = parse_dimacs("tests/test1.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let original = var_ordering.build_clauses(&dimacs.clauses, &FileOrder);
+        let size = original.size();
+        let mut stats = Stats::new();
+
+        let mut rounded = original.clone();
+        rounded.round_down(&mut stats);
+        let mut heavy_branch = original.clone();
+        heavy_branch.heavy_branch_subset(size / 2, &mut stats);
+        let mut short_path = original.clone();
+        short_path.short_path_subset(size / 2, &mut stats);
+
+        for bdd in [&rounded, &heavy_branch, &short_path] {
+            assert!(!bdd.is_false());
+            assert_ne!(bdd, &original);
+            // every model of the approximation is a model of the original
+            assert!(var_ordering.and(bdd, &original.negate()).is_false());
+        }
+        assert!(heavy_branch.size() <= size / 2);
+        assert!(short_path.size() <= size / 2);
+        assert!(stats.approximation_steps().iter().all(|step| !step.over));
+
+        // the only one edge of a literal is not redirected
+        let mut literal = var_ordering.build(&mut Expr::parse_var(&1));
+        literal.round_down(&mut stats);
+        assert_eq!(literal, var_ordering.build(&mut Expr::parse_var(&1)));
+    }
 
+    #[test]
+    fn verify_approximations() {
+        let dimacs = parse_dimacs("tests/test1.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let original = var_ordering.build_clauses(&dimacs.clauses, &FileOrder);
+        let mut stats = Stats::new();
+        assert_eq!(original.check_invariants(&var_ordering), Ok(()));
+
+        let mut subset = original.clone();
+        subset.heavy_branch_subset(original.size() / 2, &mut stats);
+        assert_eq!(subset.check_invariants(&var_ordering), Ok(()));
+        assert!(var_ordering.verify_over_approximation(&subset, &original));
+        assert!(!var_ordering.verify_over_approximation(&original, &subset));
+        assert!(var_ordering.verify_over_approximation(&original, &original));
+    }
+
+    #[test]
+    fn invariant_violations() {
+        // the levels are 4 2 5 1 3
+        let dimacs = parse_dimacs("tests/test5.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let manager = var_ordering.manager();
+        // push the nodes as they are, without reducing them
+        let push = |var: i32, low: BddPointer, high: BddPointer| {
+            let mut manager = manager.write().unwrap();
+            manager.push_node(BddNode::mk_node(BddVar(var), low, high));
+            BddPointer::new(manager.size() - 1)
+        };
+        let check = |root: BddPointer| Bdd::from_root(manager, root).check_invariants(&var_ordering);
+        let (zero, one) = (BddPointer::new_zero(), BddPointer::new_one());
+
+        let x3 = push(3, zero, one);
+        assert_eq!(check(x3), Ok(()));
+        let duplicate = push(3, zero, one);
+        assert!(check(push(1, x3, duplicate)).unwrap_err().contains("equal"));
+        assert!(check(push(1, x3, x3)).unwrap_err().contains("redundant"));
+        assert!(check(push(1, one, zero)).unwrap_err().contains("complemented"));
+        assert!(check(push(5, zero, push(4, zero, one))).unwrap_err().contains("ordering"));
+        assert!(check(push(1, zero, BddPointer::new(1000))).unwrap_err().contains("outside"));
+        assert!(check(push(6, zero, one)).unwrap_err().contains("not in the ordering"));
+    }
+
+    #[test]
+    fn redirect_reduces_in_one_pass() {
+        let (zero, one) = (BddPointer::new_zero(), BddPointer::new_one());
+        let mut detached = DetachedBdd::new();
+        detached.push_node(BddNode::mk_node(BddVar(3), zero, one));
+        // x2 and x3
+        detached.push_node(BddNode::mk_node(BddVar(2), zero, BddPointer(2)));
+        detached.push_node(BddNode::mk_node(BddVar(2), zero, one));
+        detached.push_node(BddNode::mk_node(BddVar(1), BddPointer(3), BddPointer(4)));
+
+        // x2 and x3 becomes x2, equal to the other child of the root, which becomes
+        // redundant, and x3 is no longer reachable and left behind
+        let rebuilt = detached.redirect(&[(BddPointer(3), true, one)]).unwrap();
+        assert_eq!(rebuilt.size(), 3);
+        assert_eq!(rebuilt.var_of_ptr(rebuilt.root_pointer()), BddVar(2));
+        // without redirects the diagram is reduced already and all the nodes stay
+        assert_eq!(detached.redirect(&[]).unwrap().size(), 6);
+
+        assert!(detached.redirect(&[(BddPointer(5), false, zero), (BddPointer(5), true, zero)]).is_none());
+        assert!(detached.redirect(&[(BddPointer(5), false, one), (BddPointer(5), true, one)]).unwrap().is_true());
+    }
+
+    #[test]
+    fn round_up_until_true() {
+        let dimacs = parse_dimacs("tests/test1.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let mut bdd = var_ordering.build_clauses(&dimacs.clauses, &FileOrder);
+        let mut stats = Stats::new();
+        while !bdd.is_true() {
+            let previous = bdd.clone();
+            bdd.round_up(&mut stats);
+            assert!(bdd.size() < previous.size());
+            assert!(var_ordering.verify_over_approximation(&previous, &bdd));
+            assert_eq!(bdd.check_invariants(&var_ordering), Ok(()));
+        }
+    }
 
     #[test]
     pub fn test_tauto_red_1() {
@@ -247,12 +758,17 @@ mod tests {
         let node4 = BddNode::mk_node(BddVar(1), BddPointer(0), BddPointer(3));
 
 
-        let mut bdd = Bdd::new();
+        let mut bdd = DetachedBdd::new();
         bdd.push_node(node2);
         bdd.push_node(node3);
         bdd.push_node(node4);
 
         bdd.tauto_reduction();
+        // x1 is decided above the second node of x1, which is skipped
+        assert_eq!(bdd.size(), 4);
+        let x2 = bdd.high_node_ptr(bdd.root_pointer());
+        assert_eq!(bdd.var_of_ptr(x2), BddVar(2));
+        assert_eq!(bdd.high_node_ptr(x2), BddPointer::new_one());
     }
     #[test]
     pub fn test_tauto_red_2() {
@@ -265,7 +781,7 @@ mod tests {
         let node7 = BddNode::mk_node(BddVar(1), BddPointer(6), BddPointer(5));
 
 
-        let mut bdd = Bdd::new();
+        let mut bdd = DetachedBdd::new();
         bdd.push_node(node2);
         bdd.push_node(node3);
         bdd.push_node(node4);
@@ -274,5 +790,8 @@ mod tests {
         bdd.push_node(node7);
 
         bdd.tauto_reduction();
+        // the node of x1 below the low edge of the root and the node of x1 below the
+        // high edges are skipped, the other node of x1 is reached with x1 decided both ways
+        assert_eq!(bdd.size(), 6);
     }
 }
\ No newline at end of file
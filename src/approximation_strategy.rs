@@ -0,0 +1,547 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::time::Instant;
+use crossbeam_channel::Receiver;
+use crate::bdd::Bdd;
+use crate::bdd_manager::{BddManager, DetachedBdd};
+use crate::bdd_util::{BddPointer, BddVar};
+use crate::statistics::stats::{ApproximationStep, Stats};
+use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+/// An algorithm to approximate the Bdd being built when it grows too big.
+/// The approximation is an over-approximation: every model of the Bdd stays
+/// a model, so the clauses read off the approximation, like the learned
+/// clauses sent to Glucose, are still implied by the formula.
+///
+/// The classic subsetting algorithms under-approximate a function. They
+/// over-approximate the Bdd by under-approximating its negation.
+pub trait ApproximationStrategy: Debug + Send + Sync {
+
+    /// Over-approximate the Bdd, down to at most `threshold` nodes if the
+    /// strategy gets that far. The steps are recorded in the statistics.
+    /// The `ordering` is the one of the Bdd, for the strategies that build nodes.
+    fn approximate(&self, bdd: &mut Bdd, ordering: &BddVarOrdering, threshold: usize, stats: &mut Stats, receiver: Receiver<()>);
+}
+
+/// Get an approximation strategy by its name: `round-up`, `heavy-branch`,
+/// `short-path`, `remap`, `bit-dependent` or `density`.
+pub fn approximation_strategy(name: &str) -> Option<Box<dyn ApproximationStrategy>> {
+    match name {
+        "round-up" => Some(Box::new(RoundUp)),
+        "heavy-branch" => Some(Box::new(HeavyBranch)),
+        "short-path" => Some(Box::new(ShortPath)),
+        "remap" => Some(Box::new(RemapUnderApproximation)),
+        "bit-dependent" => Some(Box::new(BitDependent)),
+        "density" => Some(Box::new(DensitySubsetting)),
+        _ => None,
+    }
+}
+
+/// Round up the node with the smallest off-set, step by step, see `Bdd::round_up`.
+#[derive(Debug)]
+pub struct RoundUp;
+
+impl ApproximationStrategy for RoundUp {
+    fn approximate(&self, bdd: &mut Bdd, _ordering: &BddVarOrdering, threshold: usize, stats: &mut Stats, receiver: Receiver<()>) {
+        bdd.approximate_to(threshold, stats, receiver);
+    }
+}
+
+/// Heavy-branch subsetting of the negation, see `Bdd::heavy_branch_subset`.
+#[derive(Debug)]
+pub struct HeavyBranch;
+
+impl ApproximationStrategy for HeavyBranch {
+    fn approximate(&self, bdd: &mut Bdd, _ordering: &BddVarOrdering, threshold: usize, stats: &mut Stats, _receiver: Receiver<()>) {
+        bdd.redirect_edges(true, stats, |detached| detached.heavy_branch_subset(threshold));
+    }
+}
+
+/// Short-path subsetting of the negation, see `Bdd::short_path_subset`.
+#[derive(Debug)]
+pub struct ShortPath;
+
+impl ApproximationStrategy for ShortPath {
+    fn approximate(&self, bdd: &mut Bdd, _ordering: &BddVarOrdering, threshold: usize, stats: &mut Stats, _receiver: Receiver<()>) {
+        bdd.redirect_edges(true, stats, |detached| detached.short_path_subset(threshold));
+    }
+}
+
+/// Remap under-approximation (RUA) of the negation. From the root down, a node
+/// is replaced by zero, or remapped to one of its children if that child
+/// implies the other one, whichever raises the density of the diagram the
+/// most. The density is the share of the models per node. Stops as soon as
+/// the diagram fits. If the diagram still does not fit once no replacement
+/// raises the density anymore, it is subset like in `DensitySubsetting`.
+#[derive(Debug)]
+pub struct RemapUnderApproximation;
+
+impl ApproximationStrategy for RemapUnderApproximation {
+    fn approximate(&self, bdd: &mut Bdd, _ordering: &BddVarOrdering, threshold: usize, stats: &mut Stats, _receiver: Receiver<()>) {
+        bdd.redirect_edges(true, stats, |detached| {
+            let mut replacements = Replacements::new(detached);
+            replacements.raise_density(threshold, true, None);
+            replacements.fit(threshold);
+            replacements.redirected
+        });
+    }
+}
+
+/// Bit-dependent approximation (BDA) of the negation, Shiple's under-approximation.
+/// From the root down, a node is replaced by zero or by the conjunction of its
+/// two cofactors, whichever raises the density of the diagram the most. The
+/// conjunction no longer depends on the variable (the bit) of the node, and is
+/// one of the children whenever that child implies the other one. Unlike the
+/// remap under-approximation, the conjunction is built with the ordering, so
+/// it may add nodes that were not in the diagram before. If the diagram still
+/// does not fit once no replacement raises the density anymore, it is subset
+/// like in `DensitySubsetting`.
+#[derive(Debug)]
+pub struct BitDependent;
+
+impl ApproximationStrategy for BitDependent {
+    fn approximate(&self, bdd: &mut Bdd, ordering: &BddVarOrdering, threshold: usize, stats: &mut Stats, receiver: Receiver<()>) {
+        let now = Instant::now();
+        if !bdd.is_true() && !bdd.is_false() {
+            let manager_ref = bdd.manager().clone();
+            let mut manager = manager_ref.write().unwrap();
+            let size_before = manager.node_count(bdd.root_pointer());
+            let negation = bdd.root_pointer().complement();
+            let mut detached = manager.detach(negation);
+            let mut cofactors = Cofactors::new(&mut manager, ordering, negation);
+            let mut replacements = Replacements::new(&mut detached);
+            replacements.raise_density(threshold, false, Some(&mut cofactors));
+            let root = cofactors.rebuild(&replacements);
+            if !root.is_terminal() {
+                *bdd = Bdd::from_locked(&manager_ref, &manager, root.complement());
+                let size_after = manager.node_count(root);
+                let redirected = replacements.redirected.into_iter().map(|(var, high)| (var.0, high)).collect();
+                stats.add_approximation_step(ApproximationStep { redirected, over: true, size_before, size_after });
+            }
+        }
+        stats.add_t_approx(now.elapsed());
+        // the nodes added by the conjunctions are only estimated, so the result may not fit yet
+        if bdd.size() > threshold {
+            DensitySubsetting.approximate(bdd, ordering, threshold, stats, receiver);
+        }
+    }
+}
+
+/// Density-driven subsetting of the negation. The nodes are replaced by zero in
+/// the order of the fewest models lost per node saved, until the diagram fits.
+/// Unlike the remap under-approximation the density may drop, as long as the
+/// threshold is reached.
+#[derive(Debug)]
+pub struct DensitySubsetting;
+
+impl ApproximationStrategy for DensitySubsetting {
+    fn approximate(&self, bdd: &mut Bdd, _ordering: &BddVarOrdering, threshold: usize, stats: &mut Stats, _receiver: Receiver<()>) {
+        bdd.redirect_edges(true, stats, |detached| {
+            let mut replacements = Replacements::new(detached);
+            replacements.fit(threshold);
+            replacements.redirected
+        });
+    }
+}
+
+/// The replacement of nodes of a detached diagram, by zero, by one of their
+/// children or by the conjunction of their cofactors, which under-approximates the diagram. The share of the models
+/// lost by a replacement is estimated from the share of the assignments
+/// reaching the node and satisfying its children in the original diagram.
+/// The densest path to one is kept, so the diagram never becomes zero.
+struct Replacements<'a> {
+    detached: &'a mut DetachedBdd,
+    densities: Vec<f64>,
+    reach: Vec<f64>,
+    // the number of edges to every node, the root has one from the outside
+    references: Vec<usize>,
+    // the nodes on the densest path from the root to one, which are never replaced
+    protected: Vec<bool>,
+    // the nodes replaced by the conjunction of their cofactors, see `Cofactors`
+    conjoined: Vec<bool>,
+    models: f64,
+    size: usize,
+    redirected: Vec<(BddVar, bool)>,
+}
+
+/// The conjunction of the cofactors of a node, and what replacing the node by it costs.
+struct Conjunction {
+    lost: f64,
+    // the live nodes of the diagram that the conjunction shares
+    kept: Vec<BddPointer>,
+    // the functions of the conjunction that are not in the diagram yet
+    added: Vec<BddPointer>,
+}
+
+impl<'a> Replacements<'a> {
+
+    fn new(detached: &'a mut DetachedBdd) -> Replacements<'a> {
+        let size = detached.size();
+        let mut references = vec![0; size];
+        if !detached.is_true() {
+            references[size - 1] = 1;
+        }
+        for ptr in (2..size).map(BddPointer::new) {
+            references[detached.low_node_ptr(ptr).to_index()] += 1;
+            references[detached.high_node_ptr(ptr).to_index()] += 1;
+        }
+        let densities = detached.densities();
+        let reach = detached.reach_probabilities();
+        let mut protected = vec![false; size];
+        let mut ptr = detached.root_pointer();
+        while !ptr.is_terminal() {
+            protected[ptr.to_index()] = true;
+            let (low, high) = (detached.low_node_ptr(ptr), detached.high_node_ptr(ptr));
+            ptr = if densities[high.to_index()] > densities[low.to_index()] { high } else { low };
+        }
+        let models = densities[detached.root_pointer().to_index()];
+        Replacements { detached, densities, reach, references, protected, conjoined: vec![false; size], models, size, redirected: Vec::new() }
+    }
+
+    /// Replace the nodes from the root down while the density rises, until the
+    /// diagram has at most `threshold` nodes. With `cofactors`, a node may also
+    /// be replaced by the conjunction of its cofactors.
+    fn raise_density(&mut self, threshold: usize, remap: bool, mut cofactors: Option<&mut Cofactors>) {
+        for idx in (2..self.detached.size()).rev() {
+            if self.size <= threshold {
+                break;
+            }
+            if self.references[idx] == 0 || self.protected[idx] {
+                continue;
+            }
+            let ptr = BddPointer::new(idx);
+            let density = self.models / self.size as f64;
+            let mut options = vec![BddPointer::new_zero()];
+            if remap {
+                options.extend(self.remap_children(ptr));
+            }
+            let (replacement, replaced_density) = options.into_iter()
+                .map(|replacement| (replacement, self.density_after(ptr, replacement)))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap();
+            if let Some(cofactors) = cofactors.as_deref_mut() {
+                let conjunction = self.conjunction(cofactors, ptr);
+                let saved = self.freed(ptr, &conjunction.kept).len();
+                let conjoined_density = (self.models - conjunction.lost) / (self.size - saved + conjunction.added.len()) as f64;
+                if conjoined_density > replaced_density && conjoined_density >= density {
+                    self.conjoin(cofactors, ptr, conjunction);
+                    continue;
+                }
+            }
+            if replaced_density >= density {
+                self.replace(ptr, replacement);
+            }
+        }
+    }
+
+    /// Replace the nodes by zero in the order of the fewest models lost per
+    /// node saved, until the diagram has at most `threshold` nodes or only the
+    /// densest path is left.
+    fn fit(&mut self, threshold: usize) {
+        if self.size <= threshold {
+            return;
+        }
+        let mut candidates: Vec<(f64, usize)> = (2..self.detached.size())
+            .filter(|idx| self.references[*idx] > 0 && !self.protected[*idx])
+            .map(|idx| {
+                let saved = self.freed(BddPointer::new(idx), &[]).len();
+                (self.lost(BddPointer::new(idx), BddPointer::new_zero()) / saved as f64, idx)
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        for (_, idx) in candidates {
+            if self.size <= threshold {
+                break;
+            }
+            if self.references[idx] > 0 {
+                self.replace(BddPointer::new(idx), BddPointer::new_zero());
+            }
+        }
+    }
+
+    /// The children a node can be remapped to: a child that implies the other
+    /// one. This is only checked on the structure, for a terminal child and for
+    /// a child whose own children are the other child and zero.
+    fn remap_children(&self, ptr: BddPointer) -> Vec<BddPointer> {
+        let (low, high) = (self.detached.low_node_ptr(ptr), self.detached.high_node_ptr(ptr));
+        let implies = |child: BddPointer, other: BddPointer| {
+            other.is_one() || (!child.is_terminal() && {
+                let (child_low, child_high) = (self.detached.low_node_ptr(child), self.detached.high_node_ptr(child));
+                (child_low.is_zero() && child_high == other) || (child_high.is_zero() && child_low == other)
+            })
+        };
+        [(low, high), (high, low)].into_iter()
+            .filter(|(child, other)| !child.is_zero() && implies(*child, *other))
+            .map(|(child, _)| child)
+            .collect()
+    }
+
+    /// The share of the models lost by replacing the node.
+    fn lost(&self, ptr: BddPointer, replacement: BddPointer) -> f64 {
+        let idx = ptr.to_index();
+        self.reach[idx] * (self.densities[idx] - self.densities[replacement.to_index()])
+    }
+
+    /// The density of the diagram after replacing the node.
+    fn density_after(&self, ptr: BddPointer, replacement: BddPointer) -> f64 {
+        let saved = self.freed(ptr, &[replacement]).len();
+        (self.models - self.lost(ptr, replacement)) / (self.size - saved) as f64
+    }
+
+    /// The nodes no longer referenced once the node is replaced: the node itself
+    /// and the nodes only referenced below it, except for the `kept` ones.
+    fn freed(&self, ptr: BddPointer, kept: &[BddPointer]) -> Vec<usize> {
+        let mut removed: HashMap<usize, usize> = HashMap::new();
+        let mut freed = vec![ptr.to_index()];
+        let mut stack = vec![self.detached.low_node_ptr(ptr), self.detached.high_node_ptr(ptr)];
+        while let Some(child) = stack.pop() {
+            if child.is_terminal() || kept.contains(&child) {
+                continue;
+            }
+            let idx = child.to_index();
+            let count = removed.entry(idx).or_insert(0);
+            *count += 1;
+            if *count == self.references[idx] {
+                freed.push(idx);
+                stack.push(self.detached.low_node_ptr(child));
+                stack.push(self.detached.high_node_ptr(child));
+            }
+        }
+        freed
+    }
+
+    /// Drop the references of the freed nodes to their children.
+    fn release(&mut self, freed: &[usize]) {
+        for idx in freed {
+            let freed_ptr = BddPointer::new(*idx);
+            self.references[*idx] = 0;
+            for child in [self.detached.low_node_ptr(freed_ptr), self.detached.high_node_ptr(freed_ptr)] {
+                self.references[child.to_index()] = self.references[child.to_index()].saturating_sub(1);
+            }
+        }
+    }
+
+    /// Replace the node by pointing both of its edges at the replacement,
+    /// which the import into a manager reduces to the replacement itself.
+    fn replace(&mut self, ptr: BddPointer, replacement: BddPointer) {
+        let freed = self.freed(ptr, &[replacement]);
+        let parents = self.references[ptr.to_index()];
+        self.models -= self.lost(ptr, replacement);
+        self.size -= freed.len();
+        self.release(&freed);
+        // the parents of the node now reference the replacement
+        if !replacement.is_terminal() {
+            self.references[replacement.to_index()] += parents;
+        }
+
+        let var = self.detached.var_of_ptr(ptr);
+        if self.detached.low_node_ptr(ptr) != replacement {
+            self.detached.replace_low(ptr, replacement);
+            self.redirected.push((var, false));
+        }
+        if self.detached.high_node_ptr(ptr) != replacement {
+            self.detached.replace_high(ptr, replacement);
+            self.redirected.push((var, true));
+        }
+    }
+
+    /// Build the conjunction of the cofactors of the node and count the models
+    /// it loses and the nodes it shares with the diagram or adds to it.
+    fn conjunction(&self, cofactors: &mut Cofactors, ptr: BddPointer) -> Conjunction {
+        let idx = ptr.to_index();
+        let root = cofactors.conjoin(self.detached.low_node_ptr(ptr), self.detached.high_node_ptr(ptr));
+        let lost = self.reach[idx] * (self.densities[idx] - cofactors.density(root, &self.densities));
+        let (mut kept, mut added) = (Vec::new(), Vec::new());
+        let mut visited = HashSet::new();
+        let mut stack = vec![root];
+        while let Some(function) = stack.pop() {
+            if function.is_terminal() || cofactors.added.contains(&function) || !visited.insert(function) {
+                continue;
+            }
+            match cofactors.nodes.get(&function) {
+                Some(node) if self.references[*node] > 0 => kept.push(BddPointer::new(*node)),
+                _ => {
+                    added.push(function);
+                    stack.push(cofactors.manager.low_node_ptr(function));
+                    stack.push(cofactors.manager.high_node_ptr(function));
+                }
+            }
+        }
+        Conjunction { lost, kept, added }
+    }
+
+    /// Replace the node by the conjunction of its cofactors. The edges of the
+    /// node stay, the conjunction is built when the diagram is rebuilt.
+    fn conjoin(&mut self, cofactors: &mut Cofactors, ptr: BddPointer, conjunction: Conjunction) {
+        let freed = self.freed(ptr, &conjunction.kept);
+        self.models -= conjunction.lost;
+        self.size = self.size - freed.len() + conjunction.added.len();
+        self.release(&freed);
+        for node in conjunction.kept {
+            self.references[node.to_index()] += 1;
+        }
+        cofactors.added.extend(conjunction.added);
+        self.conjoined[ptr.to_index()] = true;
+
+        let var = self.detached.var_of_ptr(ptr);
+        self.redirected.push((var, false));
+        self.redirected.push((var, true));
+    }
+}
+
+/// The functions of the shared manager behind the nodes of a detached diagram,
+/// where the conjunctions of cofactors are built with the variable ordering.
+/// The manager stays locked, so no garbage collection moves these functions.
+struct Cofactors<'a> {
+    manager: &'a mut BddManager,
+    ordering: &'a BddVarOrdering,
+    // the function of every node of the detached diagram, and the other way round
+    functions: Vec<BddPointer>,
+    nodes: HashMap<BddPointer, usize>,
+    // the densities of the functions that are not in the diagram
+    densities: HashMap<BddPointer, f64>,
+    // the functions of the conjunctions made so far
+    added: HashSet<BddPointer>,
+}
+
+impl<'a> Cofactors<'a> {
+
+    /// The functions of the diagram of `root`, laid out like `BddManager::detach` lays out its copy.
+    fn new(manager: &'a mut BddManager, ordering: &'a BddVarOrdering, root: BddPointer) -> Cofactors<'a> {
+        let functions = manager.reachable(root);
+        let nodes = functions.iter().enumerate().map(|(idx, function)| (*function, idx)).collect();
+        Cofactors { manager, ordering, functions, nodes, densities: HashMap::new(), added: HashSet::new() }
+    }
+
+    /// The conjunction of the functions of two nodes of the diagram.
+    fn conjoin(&mut self, low: BddPointer, high: BddPointer) -> BddPointer {
+        let (low, high) = (self.functions[low.to_index()], self.functions[high.to_index()]);
+        self.ordering.ite_rec(self.manager, low, high, BddPointer::new_zero())
+    }
+
+    /// The share of the assignments satisfying a function, taken from the
+    /// `diagram` densities for the functions of the diagram.
+    fn density(&mut self, function: BddPointer, diagram: &[f64]) -> f64 {
+        if let Some(idx) = self.nodes.get(&function) {
+            return diagram[*idx];
+        }
+        if function.is_complemented() {
+            return 1.0 - self.density(function.complement(), diagram);
+        }
+        if let Some(density) = self.densities.get(&function) {
+            return *density;
+        }
+        let (low, high) = (self.manager.low_node_ptr(function), self.manager.high_node_ptr(function));
+        let density = (self.density(low, diagram) + self.density(high, diagram)) / 2.0;
+        self.densities.insert(function, density);
+        density
+    }
+
+    /// Build the approximated diagram in the shared manager, bottom-up from the
+    /// nodes of the detached diagram, and return its root. The conjoined nodes
+    /// become the conjunction of their rebuilt cofactors.
+    fn rebuild(self, replacements: &Replacements) -> BddPointer {
+        let detached = &replacements.detached;
+        let size = detached.size();
+        let mut live = vec![false; size];
+        live[size - 1] = true;
+        for ptr in (2..size).rev().map(BddPointer::new) {
+            if live[ptr.to_index()] {
+                live[detached.low_node_ptr(ptr).to_index()] = true;
+                live[detached.high_node_ptr(ptr).to_index()] = true;
+            }
+        }
+        let mut renaming = vec![BddPointer::new_zero(); size];
+        renaming[1] = BddPointer::new_one();
+        for ptr in (2..size).map(BddPointer::new).filter(|ptr| live[ptr.to_index()]) {
+            let low = renaming[detached.low_node_ptr(ptr).to_index()];
+            let high = renaming[detached.high_node_ptr(ptr).to_index()];
+            renaming[ptr.to_index()] = if replacements.conjoined[ptr.to_index()] {
+                self.ordering.ite_rec(self.manager, low, high, BddPointer::new_zero())
+            } else {
+                self.manager.mk_node(detached.var_of_ptr(ptr), low, high)
+            };
+        }
+        renaming[size - 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::unbounded;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::parser::parse::parse_dimacs;
+    use crate::variable_ordering::clause_schedule::FileOrder;
+    use crate::variable_ordering::var_ordering::BddVarOrdering;
+    use super::*;
+
+    #[test]
+    fn strategies_over_approximate() {
+        let dimacs = parse_dimacs("tests/test1.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let original = var_ordering.build_clauses(&dimacs.clauses, &FileOrder);
+        let size = original.size();
+        let (_sender, receiver) = unbounded();
+
+        for name in ["round-up", "heavy-branch", "short-path", "remap", "bit-dependent", "density"] {
+            let mut stats = Stats::new();
+            let mut bdd = original.clone();
+            approximation_strategy(name).unwrap().approximate(&mut bdd, &var_ordering, size / 2, &mut stats, receiver.clone());
+            assert_ne!(bdd, original, "{}", name);
+            // every model of the original is still a model
+            assert!(var_ordering.and(&original, &bdd.negate()).is_false(), "{}", name);
+            assert!(stats.approximation_steps().iter().all(|step| step.over), "{}", name);
+        }
+        assert!(approximation_strategy("random").is_none());
+    }
+
+    #[test]
+    fn strategies_differ_and_fit() {
+        let mut dimacs = parse_dimacs("tests/test4.cnf");
+        dimacs.order = Some(dimacs.vars.clone());
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let mut rng = StdRng::seed_from_u64(2);
+        // random clauses over the variables of the instance, whose Bdd has a few
+        // hundred nodes and many models, so the strategies have room to differ
+        let clauses: Vec<Vec<i32>> = (0..10)
+            .map(|_| (0..3).map(|_| dimacs.vars[rng.gen_range(0..dimacs.vars.len())] * if rng.gen_bool(0.5) { 1 } else { -1 }).collect())
+            .collect();
+        let original = var_ordering.build_clauses(&clauses, &FileOrder);
+        let size = original.size();
+        let (_sender, receiver) = unbounded();
+
+        let mut results: Vec<(&str, Bdd)> = Vec::new();
+        for name in ["round-up", "heavy-branch", "short-path", "remap", "bit-dependent", "density"] {
+            let mut bdd = original.clone();
+            approximation_strategy(name).unwrap().approximate(&mut bdd, &var_ordering, size / 2, &mut Stats::new(), receiver.clone());
+            assert!(bdd.size() <= size / 2, "{} has {} of {} nodes", name, bdd.size(), size);
+            assert!(var_ordering.and(&original, &bdd.negate()).is_false(), "{}", name);
+            results.push((name, bdd));
+        }
+        for (i, (name, bdd)) in results.iter().enumerate() {
+            for (other_name, other) in &results[i + 1..] {
+                assert_ne!(bdd, other, "{} and {}", name, other_name);
+            }
+        }
+    }
+
+    #[test]
+    fn density_subsetting_fits_the_threshold() {
+        let dimacs = parse_dimacs("tests/test1.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let original = var_ordering.build_clauses(&dimacs.clauses, &FileOrder);
+        let (_sender, receiver) = unbounded();
+
+        // set the strategy of the manager, as used by `parallel_build`
+        let mut bdd = original.clone();
+        let size = original.size();
+        let mut manager = var_ordering.manager().write().unwrap();
+        manager.enable_approximation(size - 1, size / 2);
+        manager.set_approximation_strategy(Box::new(DensitySubsetting));
+        drop(manager);
+        assert!(bdd.approximate_if_needed(size, &var_ordering, &mut Stats::new(), receiver));
+        assert!(bdd.size() <= size / 2);
+        assert!(var_ordering.and(&original, &bdd.negate()).is_false());
+    }
+}
@@ -1,44 +1,45 @@
 use std::collections::HashMap;
-use std::iter::Map;
-use std::ops::Range;
+use std::sync::Arc;
 use rand::Rng;
-use crate::bdd_util::{BddNode, BddPointer, BddVar};
+use crate::bdd_manager::{BddManager, BddManagerRef, BddRoot};
+use crate::bdd_util::{BddPointer, BddVar};
 use rand::seq::SliceRandom;
 use crate::expr::bool_expr::Expr;
+use crate::variable_ordering::var_ordering::BddVarOrdering;
 
 // The Bdd receives the clauses 'Vec<Vec<i32>>'. They can be viewed as a boolean
 // expression for example (x1 OR x2) AND (NOT x1 OR x2). Then the INF (if then else normalform)
 // needs to be found for this expression so that the Bdd can be constructed.
 
-#[derive(Clone, Debug, Eq)]
-pub struct Bdd(pub Vec<BddNode>);
+/// A `Bdd` is a handle on a diagram stored in a shared `BddManager`: the pointer
+/// of its root node plus a reference to the manager. Cloning a `Bdd` is cheap and
+/// `Bdd`s built in the same manager share all their common sub-diagrams.
+/// The root is registered in the manager, which keeps its nodes alive and moves
+/// the root along when garbage collection compacts the arena.
+#[derive(Clone)]
+pub struct Bdd {
+    root: Arc<BddRoot>,
+    manager: BddManagerRef,
+}
 
 impl Bdd {
 
-    /// Create a new empty Bdd. The terminal pointers are
-    /// inserted into the vector of nodes.
-    pub fn new() -> Bdd {
-        let mut nodes = Vec::new();
-        // Maximum number as pointer as in the apply method always the smaller var is
-        // selected and we want to replace these nodes.
-        let max_ptr = BddVar::new(i32::MAX);
-        nodes.push(BddNode::mk_zero(max_ptr));
-        nodes.push(BddNode::mk_one(max_ptr));
-        Bdd(nodes)
+    /// Create a new `Bdd` from the pointer of a root node inside the given manager.
+    pub fn from_root(manager: &BddManagerRef, root: BddPointer) -> Bdd {
+        let guard = manager.read().unwrap();
+        Bdd::from_locked(manager, &guard, root)
     }
 
-    pub fn new_with_capacity(cap: usize) -> Bdd {
-        let mut nodes = Vec::with_capacity(cap);
-        // Maximum number as pointer as in the apply method always the smaller var is
-        // selected and we want to replace these nodes.
-        let max_ptr = BddVar::new(i32::MAX);
-        nodes.push(BddNode::mk_zero(max_ptr));
-        nodes.push(BddNode::mk_one(max_ptr));
-        Bdd(nodes)
+    /// Like `from_root`, for a caller that already holds the lock of the manager.
+    /// The root must be registered before the lock is released, otherwise its
+    /// nodes could be collected in the meantime.
+    pub(crate) fn from_locked(manager_ref: &BddManagerRef, manager: &BddManager, root: BddPointer) -> Bdd {
+        Bdd { root: manager.register_root(root), manager: manager_ref.clone() }
     }
 
-    pub fn is_full(&self) -> bool {
-        self.0.capacity().eq(&self.0.len())
+    /// The manager the nodes of this `Bdd` are stored in.
+    pub fn manager(&self) -> &BddManagerRef {
+        &self.manager
     }
 
     pub fn is_empty(&self) -> bool {
@@ -47,120 +48,233 @@ impl Bdd {
 
     /// Get the variable of a specific pointer in the Bdd.
     pub fn var_of_ptr(&self, ptr: BddPointer) -> BddVar {
-        self.0[ptr.to_index()].var
-    }
-
-    /// Insert a node into the vector of nodes of the Bdd.
-    pub fn push_node(&mut self, node: BddNode) {
-        self.0.push(node);
+        self.manager.read().unwrap().var_of_ptr(ptr)
     }
 
     /// Create a new Bdd from a variable and connect it to terminal pointers 0 and 1.
-    pub fn new_var(var: BddVar) -> Bdd {
-        let mut bdd = Bdd::new();
-        bdd.push_node(BddNode::mk_node(var, BddPointer::new_zero(), BddPointer::new_one()));
-        bdd
+    pub fn new_var(manager: &BddManagerRef, var: BddVar) -> Bdd {
+        let mut guard = manager.write().unwrap();
+        let root = guard.mk_node(var, BddPointer::new_zero(), BddPointer::new_one());
+        Bdd::from_locked(manager, &guard, root)
     }
 
     /// Create a new Bdd from a boolean value.
-    pub fn new_value(var: BddVar, value: &bool) -> Bdd {
-        if *value { Bdd::new_true(var) } else { Bdd::new_false(var) }
+    pub fn new_value(manager: &BddManagerRef, value: &bool) -> Bdd {
+        if *value { Bdd::new_true(manager) } else { Bdd::new_false(manager) }
     }
 
     /// Create a new Bdd for the false formula.
-    pub fn new_false(var: BddVar) -> Bdd {
-        let mut bdd = Bdd::new();
-        bdd.push_node(BddNode::mk_zero(var));
-        bdd
+    pub fn new_false(manager: &BddManagerRef) -> Bdd {
+        Bdd::from_root(manager, BddPointer::new_zero())
     }
 
     /// Create a new Bdd for the true formula.
-    pub fn new_true(var: BddVar) -> Bdd {
-        let mut bdd = Bdd::new();
-        bdd.push_node(BddNode::mk_zero(var));
-        bdd.push_node(BddNode::mk_one(var));
-        bdd
+    pub fn new_true(manager: &BddManagerRef) -> Bdd {
+        Bdd::from_root(manager, BddPointer::new_one())
     }
 
     /// Create a new Bdd for a negated variable.
-    pub fn new_not_var(var: BddVar) -> Bdd {
-        let mut bdd = Bdd::new();
-        bdd.push_node(BddNode::mk_node(var, BddPointer::new_one(), BddPointer::new_zero()));
-        bdd
-    }
-
-    /// Negate a Bdd.
-    pub fn negate(&mut self) -> Bdd {
-        if self.is_true() {
-            Bdd::new_false(BddVar::new(i32::MAX))
-        } else if self.is_false() {
-            Bdd::new_true(BddVar::new(i32::MAX))
-        } else {
-            let mut nodes = self.0.clone();
-            for node in nodes.iter_mut().skip(2) {
-                // skip terminals
-                node.high.flip_if_terminal();
-                node.low.flip_if_terminal();
-            }
-            Bdd(nodes)
-        }
+    pub fn new_not_var(manager: &BddManagerRef, var: BddVar) -> Bdd {
+        let mut guard = manager.write().unwrap();
+        let root = guard.mk_node(var, BddPointer::new_one(), BddPointer::new_zero());
+        Bdd::from_locked(manager, &guard, root)
+    }
+
+    /// Negate a Bdd. This only toggles the complement tag of the root
+    /// pointer, so it is O(1) and the negation shares all the nodes.
+    pub fn negate(&self) -> Bdd {
+        let manager = self.manager.read().unwrap();
+        Bdd::from_locked(&self.manager, &manager, self.root_pointer().complement())
     }
 
-    /// The number of nodes in a Bdd.
-    pub fn size(&self) -> usize { self.0.len() }
+    /// The number of nodes in a Bdd, including the two terminals.
+    pub fn size(&self) -> usize {
+        let manager = self.manager.read().unwrap();
+        manager.node_count(self.root_pointer())
+    }
 
     /// True if a Bdd is exactly the true formula.
-    pub fn is_true(&self) -> bool { self.0.len() == 2 }
+    pub fn is_true(&self) -> bool { self.root_pointer().is_one() }
 
     /// True if a Bdd is exactly the false formula.
-    pub fn is_false(&self) -> bool { self.0.len() == 1 }
+    pub fn is_false(&self) -> bool { self.root_pointer().is_zero() }
 
-    /// Get the pointer of the root node of the Bdd.
+    /// Get the pointer of the root node of the Bdd. Garbage collection can move
+    /// the root, so the pointer is only stable while the manager is locked.
     pub fn root_pointer(&self) -> BddPointer {
-        if self.is_false() {
-            BddPointer::new_zero()
-        } else if self.is_true() {
-            BddPointer::new_one()
-        } else {
-            BddPointer::new(self.0.len() - 1)
-        }
+        self.root.get()
     }
 
-    pub fn indices(&self) -> Map<Range<usize>, fn(usize) -> BddPointer> {
-        (0..self.size()).map(BddPointer::new)
+    /// The pointers of the nodes of the Bdd, ordered bottom-up
+    /// so that the terminals come first and the root comes last.
+    /// A node used through a complemented edge is listed with its
+    /// complemented pointer, as it stands for another function there.
+    pub fn indices(&self) -> Vec<BddPointer> {
+        let manager = self.manager.read().unwrap();
+        manager.reachable(self.root_pointer())
     }
 
-    pub fn low_node_ptr(&self, ptr: BddPointer) -> BddPointer { self.0[ptr.to_index()].low }
+    /// The variables the Bdd depends on, in increasing order.
+    pub fn support(&self) -> Vec<i32> {
+        let manager = self.manager.read().unwrap();
+        let mut support: Vec<i32> = manager.reachable(self.root_pointer()).into_iter().skip(2)
+            .map(|ptr| manager.var_of_ptr(ptr).0)
+            .collect();
+        support.sort();
+        support.dedup();
+        support
+    }
 
-    pub fn replace_low(&mut self, ptr: BddPointer, new_ptr: BddPointer) { self.0[ptr.to_index()].low = new_ptr }
+    /// The low child of a pointer, with its complement tag pushed down.
+    pub fn low_node_ptr(&self, ptr: BddPointer) -> BddPointer { self.manager.read().unwrap().low_node_ptr(ptr) }
 
+    /// The high child of a pointer, with its complement tag pushed down.
     pub fn high_node_ptr(&self, ptr: BddPointer) -> BddPointer {
-        self.0[ptr.to_index()].high
+        self.manager.read().unwrap().high_node_ptr(ptr)
     }
 
-    pub fn replace_high(&mut self, ptr: BddPointer, new_ptr: BddPointer) { self.0[ptr.to_index()].high = new_ptr }
+    /// Restrict the Bdd by a partial assignment: every assigned variable is replaced
+    /// by its value, that is each of its nodes is bypassed in favour of the child
+    /// selected by the value. The result no longer depends on the assigned variables.
+    pub fn restrict(&self, assignment: &HashMap<i32, bool>) -> Bdd {
+        let mut manager = self.manager.write().unwrap();
+        let mut finished: HashMap<BddPointer, BddPointer> = HashMap::new();
+        let root = Bdd::restrict_rec(&mut manager, self.root_pointer(), assignment, &mut finished);
+        Bdd::from_locked(&self.manager, &manager, root)
+    }
 
-    pub fn delete_node(&mut self, to_delete: BddPointer, node_path: Vec<(BddPointer,bool)>) {
-        self.0.remove(to_delete.to_index());
-        // the path until the node to delete was reached
-        for (node, assign) in node_path.into_iter().skip(1) { // skip the first one as it was already assigned
-            if assign { // if true then decrement the high nodes
-                self.replace_high(node, BddPointer(self.high_node_ptr(node).0-1));
-            } else { // if false then decrement the low nodes
-                self.replace_low(node, BddPointer(self.low_node_ptr(node).0-1));
+    fn restrict_rec(manager: &mut BddManager, ptr: BddPointer, assignment: &HashMap<i32, bool>,
+                    finished: &mut HashMap<BddPointer, BddPointer>) -> BddPointer {
+        if ptr.is_terminal() {
+            return ptr;
+        }
+        if let Some(result) = finished.get(&ptr) {
+            return *result;
+        }
+        let var = manager.var_of_ptr(ptr);
+        let (low, high) = (manager.low_node_ptr(ptr), manager.high_node_ptr(ptr));
+        let result = match assignment.get(&var.0) {
+            Some(true) => Bdd::restrict_rec(manager, high, assignment, finished),
+            Some(false) => Bdd::restrict_rec(manager, low, assignment, finished),
+            None => {
+                let new_low = Bdd::restrict_rec(manager, low, assignment, finished);
+                let new_high = Bdd::restrict_rec(manager, high, assignment, finished);
+                manager.mk_node(var, new_low, new_high)
             }
+        };
+        finished.insert(ptr, result);
+        result
+    }
+
+    /// The generalized cofactor of the Bdd with respect to the care set `care`
+    /// (the constrain operator of Coudert and Madre). The result agrees with the
+    /// Bdd on every assignment of `care` and is usually smaller; conjoined with
+    /// `care` it gives the same function as the Bdd conjoined with `care`.
+    /// For a cube `care` this is the same as restricting by the cube.
+    pub fn constrain(&self, care: &Bdd, ordering: &BddVarOrdering) -> Bdd {
+        let mut manager = self.manager.write().unwrap();
+        let mut finished: HashMap<(BddPointer, BddPointer), BddPointer> = HashMap::new();
+        let root = Bdd::constrain_rec(&mut manager, ordering, self.root_pointer(), care.root_pointer(), &mut finished);
+        Bdd::from_locked(&self.manager, &manager, root)
+    }
+
+    fn constrain_rec(manager: &mut BddManager, ordering: &BddVarOrdering, f: BddPointer, c: BddPointer,
+                     finished: &mut HashMap<(BddPointer, BddPointer), BddPointer>) -> BddPointer {
+        if c.is_zero() {
+            // nothing to care about, any function will do
+            return BddPointer::new_zero();
+        }
+        if c.is_one() || f.is_terminal() {
+            return f;
         }
+        if f == c {
+            return BddPointer::new_one();
+        }
+        if f == c.complement() {
+            return BddPointer::new_zero();
+        }
+        if let Some(result) = finished.get(&(f, c)) {
+            return *result;
+        }
+        let (f_level, c_level) = (ordering.level(manager, f), ordering.level(manager, c));
+        let var = if f_level <= c_level { manager.var_of_ptr(f) } else { manager.var_of_ptr(c) };
+        let (f_low, f_high) = if f_level <= c_level {
+            (manager.low_node_ptr(f), manager.high_node_ptr(f))
+        } else {
+            (f, f)
+        };
+        let (c_low, c_high) = if c_level <= f_level {
+            (manager.low_node_ptr(c), manager.high_node_ptr(c))
+        } else {
+            (c, c)
+        };
+        let result = if c_low.is_zero() {
+            Bdd::constrain_rec(manager, ordering, f_high, c_high, finished)
+        } else if c_high.is_zero() {
+            Bdd::constrain_rec(manager, ordering, f_low, c_low, finished)
+        } else {
+            let low = Bdd::constrain_rec(manager, ordering, f_low, c_low, finished);
+            let high = Bdd::constrain_rec(manager, ordering, f_high, c_high, finished);
+            manager.mk_node(var, low, high)
+        };
+        finished.insert((f, c), result);
+        result
     }
 
-    pub fn replace_node(&mut self, to_delete: BddPointer, replacement: BddPointer) {
-        self.0.remove(to_delete.to_index());
-        for ptr in self.indices() {
-            if self.low_node_ptr(ptr).eq(&to_delete) {
-                self.replace_low(ptr, replacement);
-            } else if self.high_node_ptr(ptr).eq(&to_delete) {
-                self.replace_high(ptr, replacement);
-            }
+    /// Functional composition: substitute the function `g` for the variable `var`,
+    /// which gives $\phi[var := g] = (g \land \phi|_{var=1}) \lor (\neg g \land \phi|_{var=0})$.
+    pub fn compose(&self, var: i32, g: &Bdd, ordering: &BddVarOrdering) -> Bdd {
+        let mut substitution = HashMap::new();
+        substitution.insert(var, g.clone());
+        self.vector_compose(&substitution, ordering)
+    }
+
+    /// Substitute the given functions for their variables, all at the same time.
+    /// The variables which are not in the substitution are kept as they are.
+    pub fn vector_compose(&self, substitution: &HashMap<i32, Bdd>, ordering: &BddVarOrdering) -> Bdd {
+        let mut manager = self.manager.write().unwrap();
+        let substitution: HashMap<i32, BddPointer> = substitution.iter()
+            .map(|(var, bdd)| (*var, bdd.root_pointer()))
+            .collect();
+        let mut finished: HashMap<BddPointer, BddPointer> = HashMap::new();
+        let root = Bdd::compose_rec(&mut manager, ordering, self.root_pointer(), &substitution, &mut finished);
+        Bdd::from_locked(&self.manager, &manager, root)
+    }
+
+    /// Rename the variables of the Bdd, so that each variable `var` in `renaming`
+    /// is replaced by the variable `renaming[var]`. The new variables have to be
+    /// in the ordering and the renaming does not need to preserve the ordering.
+    ///
+    /// *Panics*:
+    ///  - If a new variable is not in the ordering.
+    pub fn rename_vars(&self, renaming: &HashMap<i32, i32>, ordering: &BddVarOrdering) -> Bdd {
+        let substitution: HashMap<i32, Bdd> = renaming.iter()
+            .map(|(var, new_var)| (*var, Bdd::new_var(&self.manager, BddVar::new(*new_var))))
+            .collect();
+        self.vector_compose(&substitution, ordering)
+    }
+
+    fn compose_rec(manager: &mut BddManager, ordering: &BddVarOrdering, ptr: BddPointer,
+                   substitution: &HashMap<i32, BddPointer>, finished: &mut HashMap<BddPointer, BddPointer>) -> BddPointer {
+        if ptr.is_terminal() {
+            return ptr;
         }
+        if let Some(result) = finished.get(&ptr) {
+            return *result;
+        }
+        let var = manager.var_of_ptr(ptr);
+        let (low, high) = (manager.low_node_ptr(ptr), manager.high_node_ptr(ptr));
+        let new_low = Bdd::compose_rec(manager, ordering, low, substitution, finished);
+        let new_high = Bdd::compose_rec(manager, ordering, high, substitution, finished);
+        // The children may now depend on variables above this one,
+        // so the node is rebuilt with an if-then-else instead of mk_node.
+        let condition = match substitution.get(&var.0) {
+            Some(g) => *g,
+            None => manager.mk_node(var, BddPointer::new_zero(), BddPointer::new_one()),
+        };
+        let result = ordering.ite_rec(manager, condition, new_high, new_low);
+        finished.insert(ptr, result);
+        result
     }
 
     /// Check if the Bdd is satisfiable and if its the case return
@@ -172,27 +286,20 @@ impl Bdd {
         }
         // Initialise the final assignment with a capacity of the total number of variables.
         let mut assignment: HashMap<i32, bool> = HashMap::with_capacity(ordered_vars.len() as usize);
-        let mut acc = BddPointer::new_one();
-
-        // Search the Bdd backwards starting from the one pointer.
-        for ptr in self.indices() {
-
-            if ptr.is_terminal() {
-                // skip the terminal nodes
-                continue;
-            }
-            if self.low_node_ptr(ptr) == acc {
-                // push front as we go backwards and assign the variables
-                // from the last to the first.
-                let var = self.var_of_ptr(ptr).0;
-                assignment.insert(var, false);
-                acc = ptr;
-            }
-            if self.high_node_ptr(ptr) == acc {
-                let var = self.var_of_ptr(ptr).0;
+        let manager = self.manager.read().unwrap();
+        let mut acc = self.root_pointer();
+
+        // Search the Bdd from the root down to the one pointer. In a reduced
+        // Bdd every node except 0 has a path to 1, so we only need to avoid 0.
+        // The children are read through the manager, which follows the complement tags.
+        while !acc.is_terminal() {
+            let var = manager.var_of_ptr(acc).0;
+            if manager.low_node_ptr(acc).is_zero() {
                 assignment.insert(var, true);
-                // save the new pointer in the accumulator.
-                acc = ptr;
+                acc = manager.high_node_ptr(acc);
+            } else {
+                assignment.insert(var, false);
+                acc = manager.low_node_ptr(acc);
             }
         }
 
@@ -235,9 +342,99 @@ impl Bdd {
 
 impl PartialEq for Bdd {
     fn eq(&self, other: &Self) -> bool {
-        (self.size() == other.size()) &&
-            (self.0.iter().all(|x| other.0.contains(x))) &&
-            (other.0.iter().all(|y| self.0.contains(y)))
+        // Bdds in the same manager are canonical, so equal functions have the same root.
+        self.root_pointer() == other.root_pointer() && Arc::ptr_eq(&self.manager, &other.manager)
+    }
+}
+
+impl Eq for Bdd {}
 
+impl std::fmt::Debug for Bdd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let manager = self.manager.read().unwrap();
+        let nodes: Vec<String> = manager.reachable(self.root_pointer()).into_iter().skip(2)
+            .map(|ptr| format!("{}: {} → {},{}", ptr, manager.var_of_ptr(ptr),
+                               manager.low_node_ptr(ptr), manager.high_node_ptr(ptr)))
+            .collect();
+        write!(f, "Bdd(root: {}, nodes: {:?})", self.root_pointer(), nodes)
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::expr::bool_expr::Expr;
+    use crate::parser::parse::parse_dimacs;
+    use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+    #[test]
+    fn restrict_by_assignment() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let f = var_ordering.build(&mut Expr::parse_clause(&vec![1, -2]));
+        let g = var_ordering.build(&mut Expr::parse_clause(&vec![2, 3]));
+        let f_and_g = var_ordering.and(&f, &g);
+
+        let mut assignment = HashMap::new();
+        assignment.insert(2, true);
+        assert_eq!(f_and_g.restrict(&assignment), var_ordering.build(&mut Expr::Var(1)));
+        assignment.insert(1, false);
+        assert!(f_and_g.restrict(&assignment).is_false());
+
+        let mut assignment = HashMap::new();
+        assignment.insert(2, false);
+        assert_eq!(f_and_g.restrict(&assignment), var_ordering.build(&mut Expr::Var(3)));
+        assert_eq!(f_and_g.negate().restrict(&assignment), var_ordering.build(&mut Expr::Var(3)).negate());
+    }
+
+    #[test]
+    fn constrain_by_care_set() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let f = var_ordering.build(&mut Expr::parse_clause(&vec![1, -2, 4]));
+        let care = var_ordering.build(&mut Expr::parse_clause(&vec![2, 3]));
+        let constrained = f.constrain(&care, &var_ordering);
+
+        assert_eq!(var_ordering.and(&constrained, &care), var_ordering.and(&f, &care));
+        assert!(f.constrain(&f, &var_ordering).is_true());
+
+        // for a cube the generalized cofactor is the restriction
+        let cube = var_ordering.and(&var_ordering.build(&mut Expr::Var(2)),
+                                    &var_ordering.build(&mut Expr::Var(4)).negate());
+        let mut assignment = HashMap::new();
+        assignment.insert(2, true);
+        assignment.insert(4, false);
+        assert_eq!(f.constrain(&cube, &var_ordering), f.restrict(&assignment));
+    }
+
+    #[test]
+    fn compose_and_rename() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let f = var_ordering.build(&mut Expr::parse_clause(&vec![1, -2]));
+        let g = var_ordering.build(&mut Expr::parse_clause(&vec![3, 4]));
+
+        // (1 or -2)[2 := (3 or 4)] = (1 or (-3 and -4))
+        let expected = var_ordering.or(&var_ordering.build(&mut Expr::Var(1)), &g.negate());
+        assert_eq!(f.compose(2, &g, &var_ordering), expected);
+        assert_eq!(f.compose(5, &g, &var_ordering), f);
+
+        // the swap of 1 and 2 is done at the same time
+        let mut renaming = HashMap::new();
+        renaming.insert(1, 2);
+        renaming.insert(2, 1);
+        let expected = var_ordering.build(&mut Expr::parse_clause(&vec![2, -1]));
+        assert_eq!(f.rename_vars(&renaming, &var_ordering), expected);
+        assert_eq!(f.rename_vars(&renaming, &var_ordering).rename_vars(&renaming, &var_ordering), f);
+
+        let mut renaming = HashMap::new();
+        renaming.insert(1, 5);
+        renaming.insert(2, 3);
+        let expected = var_ordering.build(&mut Expr::parse_clause(&vec![5, -3]));
+        assert_eq!(f.rename_vars(&renaming, &var_ordering), expected);
+    }
+}
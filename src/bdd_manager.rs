@@ -0,0 +1,778 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use crate::approximation_strategy::{ApproximationStrategy, RoundUp};
+use crate::bdd_util::{BddNode, BddPointer, BddVar};
+use crate::variable_ordering::reordering::ReorderingMethod;
+
+/// The default number of entries of the computed table of a manager.
+pub const DEFAULT_CACHE_CAPACITY: usize = 1 << 16;
+
+/// The default number of nodes in the arena at which a manager collects garbage.
+pub const DEFAULT_GC_THRESHOLD: usize = 1 << 20;
+
+/// A reference to a `BddManager` which is shared between all the `Bdd`s
+/// built for the same variable ordering.
+pub type BddManagerRef = Arc<RwLock<BddManager>>;
+
+/// The `BddManager` owns one node arena for all the `Bdd`s of an ordering.
+///
+/// Nodes are hash-consed through a unique table, so every sub-diagram is stored
+/// exactly once and a `Bdd` is just a pointer to its root node. Edges can carry a
+/// complement tag, so a function and its negation share all their nodes. To keep
+/// the nodes canonical, the high edge of a stored node is never complemented and
+/// never points to 0. As a node can only
+/// be created after its children, the arena is always ordered bottom-up: the index
+/// of a node is bigger than the indices of its low and high children.
+///
+/// The manager knows the roots of all the live `Bdd` handles. Once the arena grows
+/// past the garbage collection threshold, the nodes that are not reachable from any
+/// of them are dropped and the arena is compacted, moving the roots along.
+#[derive(Debug)]
+pub struct BddManager {
+    nodes: Vec<BddNode>,
+    // The table H:(i,l,h) -> u, the "inverse" of the node table T.
+    unique_table: HashMap<BddNode, BddPointer>,
+    computed_table: ComputedTable<(BddOp, BddPointer, BddPointer)>,
+    // The if-then-else results have a table of their own, keyed by (f, g, h).
+    ite_table: ComputedTable<(BddPointer, BddPointer, BddPointer)>,
+    // The roots of the handles, registered behind a lock of their own
+    // so that a handle can be created while the manager is only read.
+    roots: Mutex<Vec<Weak<BddRoot>>>,
+    gc_threshold: usize,
+    reordering: Option<ReorderingMethod>,
+    reorder_threshold: usize,
+    // The node limit of a `Bdd` being built and the size it is approximated down to.
+    approximation_budget: Option<(usize, usize)>,
+    approximation_strategy: Arc<dyn ApproximationStrategy>,
+}
+
+/// The root pointer of a `Bdd` handle. It is shared by the clones of the handle
+/// and updated by the manager when garbage collection moves the root node.
+#[derive(Debug)]
+pub struct BddRoot(AtomicU32);
+
+impl BddRoot {
+
+    pub fn new(root: BddPointer) -> BddRoot {
+        BddRoot(AtomicU32::new(root.0))
+    }
+
+    pub fn get(&self) -> BddPointer {
+        BddPointer(self.0.load(Ordering::Acquire))
+    }
+
+    fn set(&self, root: BddPointer) {
+        self.0.store(root.0, Ordering::Release)
+    }
+}
+
+/// The live nodes of a manager during a reordering: the number of references
+/// to every node, the nodes of every variable and how many nodes are live.
+/// It is kept up to date by `swap_adjacent_vars`, so the size of the diagrams
+/// is known after every swap without scanning the arena.
+#[derive(Debug, Default)]
+pub(crate) struct LiveNodeTable {
+    refs: Vec<usize>,
+    // may still list nodes that died since, or list a revived node twice
+    by_var: HashMap<BddVar, Vec<usize>>,
+    count: usize,
+}
+
+impl LiveNodeTable {
+
+    /// The number of live nodes, including the terminals.
+    pub(crate) fn count(&self) -> usize { self.count }
+
+    /// The number of live nodes of a variable.
+    pub(crate) fn count_of(&self, var: BddVar) -> usize {
+        self.by_var.get(&var).map_or(0, |nodes| nodes.iter().filter(|idx| self.refs[**idx] > 0).count())
+    }
+
+    /// Take the live nodes of a variable out of the table, each of them once.
+    fn take_live(&mut self, var: BddVar) -> Vec<usize> {
+        let mut nodes = self.by_var.remove(&var).unwrap_or_default();
+        nodes.retain(|idx| self.refs[*idx] > 0);
+        nodes.sort_unstable();
+        nodes.dedup();
+        nodes
+    }
+
+    /// Add a reference to a node. A node that had none is live (again),
+    /// and so are its children.
+    fn reference(&mut self, manager: &BddManager, ptr: BddPointer) {
+        let mut stack = vec![ptr.to_index()];
+        while let Some(idx) = stack.pop() {
+            if idx >= self.refs.len() {
+                self.refs.resize(manager.nodes.len(), 0);
+            }
+            self.refs[idx] += 1;
+            if idx >= 2 && self.refs[idx] == 1 {
+                let node = manager.nodes[idx];
+                self.by_var.entry(node.var).or_default().push(idx);
+                self.count += 1;
+                stack.push(node.low.to_index());
+                stack.push(node.high.to_index());
+            }
+        }
+    }
+
+    /// Remove a reference from a node. A node without references is dead,
+    /// and its children lose the references it held.
+    fn release(&mut self, manager: &BddManager, ptr: BddPointer) {
+        let mut stack = vec![ptr.to_index()];
+        while let Some(idx) = stack.pop() {
+            self.refs[idx] -= 1;
+            if idx >= 2 && self.refs[idx] == 0 {
+                let node = manager.nodes[idx];
+                self.count -= 1;
+                stack.push(node.low.to_index());
+                stack.push(node.high.to_index());
+            }
+        }
+    }
+}
+
+/// The binary operations whose results are kept in the computed table.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum BddOp {
+    And,
+    Or,
+    Xor,
+    Imp,
+    Iff,
+    Nand,
+    Nor,
+    AndNot,
+}
+
+impl BddOp {
+    /// True if the operands of the operation can be swapped.
+    pub fn is_commutative(&self) -> bool {
+        match self {
+            BddOp::And | BddOp::Or | BddOp::Xor | BddOp::Iff | BddOp::Nand | BddOp::Nor => true,
+            BddOp::Imp | BddOp::AndNot => false,
+        }
+    }
+}
+
+/// A bounded, lossy cache of operation results, for example keyed by (operation, left, right).
+///
+/// Every key is hashed to exactly one slot and a new result simply overwrites the
+/// slot, so the table never grows. Garbage collection moves the nodes of the
+/// arena, so a pointer may stand for another node afterwards: the manager clears
+/// its tables on every compaction, which keeps the results in them valid.
+#[derive(Clone, Debug)]
+pub struct ComputedTable<K> {
+    entries: Vec<Option<(K, BddPointer)>>,
+}
+
+impl<K: Copy + Eq + Hash> ComputedTable<K> {
+
+    /// Create a new table with (at least) the given number of slots.
+    /// A table with no slots caches nothing.
+    pub fn new(capacity: usize) -> ComputedTable<K> {
+        let capacity = if capacity == 0 { 0 } else { capacity.next_power_of_two() };
+        ComputedTable { entries: vec![None; capacity] }
+    }
+
+    fn slot(&self, key: &K) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() as usize) & (self.entries.len() - 1)
+    }
+
+    pub fn get(&self, key: &K) -> Option<BddPointer> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        match self.entries[self.slot(key)] {
+            Some((k, result)) if k == *key => Some(result),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, key: K, result: BddPointer) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let slot = self.slot(&key);
+        self.entries[slot] = Some((key, result));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+}
+
+impl Default for BddManager {
+    fn default() -> Self {
+        BddManager::new()
+    }
+}
+
+impl BddManager {
+
+    /// Create a new manager holding only the terminal nodes 0 and 1.
+    pub fn new() -> BddManager {
+        BddManager::with_cache_capacity(DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Create a new manager whose computed table has the given number of slots.
+    pub fn with_cache_capacity(cache_capacity: usize) -> BddManager {
+        let mut nodes = Vec::new();
+        // Maximum number as pointer as in the apply method always the smaller var is
+        // selected and we want to replace these nodes.
+        let max_ptr = BddVar::new(i32::MAX);
+        nodes.push(BddNode::mk_zero(max_ptr));
+        nodes.push(BddNode::mk_one(max_ptr));
+        BddManager {
+            nodes,
+            unique_table: HashMap::new(),
+            computed_table: ComputedTable::new(cache_capacity),
+            ite_table: ComputedTable::new(cache_capacity),
+            roots: Mutex::new(Vec::new()),
+            gc_threshold: DEFAULT_GC_THRESHOLD,
+            reordering: None,
+            reorder_threshold: usize::MAX,
+            approximation_budget: None,
+            approximation_strategy: Arc::new(RoundUp),
+        }
+    }
+
+    /// Create a new manager wrapped into a shared reference.
+    pub fn new_shared() -> BddManagerRef {
+        Arc::new(RwLock::new(BddManager::new()))
+    }
+
+    /// Return the pointer of the node (var, low, high), creating it only if
+    /// it does not exist yet. Redundant nodes (low == high) are never created.
+    /// If the high edge is complemented, the node for the negated children is
+    /// stored instead and a complemented pointer to it is returned.
+    pub fn mk_node(&mut self, var: BddVar, low: BddPointer, high: BddPointer) -> BddPointer {
+        if low == high {
+            return low;
+        }
+        if high.is_complemented() || high.is_zero() {
+            return self.mk_node(var, low.complement(), high.complement()).complement();
+        }
+        let node = BddNode::mk_node(var, low, high);
+        if let Some(ptr) = self.unique_table.get(&node) {
+            return *ptr;
+        }
+        let ptr = BddPointer::new(self.nodes.len());
+        self.nodes.push(node);
+        self.unique_table.insert(node, ptr);
+        ptr
+    }
+
+    /// Look up the result of `op` on the two pointers in the computed table.
+    pub fn cached(&self, op: BddOp, left: BddPointer, right: BddPointer) -> Option<BddPointer> {
+        let (left, right) = BddManager::cache_key(op, left, right);
+        self.computed_table.get(&(op, left, right))
+    }
+
+    /// Remember the result of `op` on the two pointers in the computed table.
+    pub fn cache_result(&mut self, op: BddOp, left: BddPointer, right: BddPointer, result: BddPointer) {
+        let (left, right) = BddManager::cache_key(op, left, right);
+        self.computed_table.insert((op, left, right), result);
+    }
+
+    /// Look up the result of ite(f, g, h) in the if-then-else table.
+    pub fn cached_ite(&self, f: BddPointer, g: BddPointer, h: BddPointer) -> Option<BddPointer> {
+        self.ite_table.get(&(f, g, h))
+    }
+
+    /// Remember the result of ite(f, g, h) in the if-then-else table.
+    pub fn cache_ite_result(&mut self, f: BddPointer, g: BddPointer, h: BddPointer, result: BddPointer) {
+        self.ite_table.insert((f, g, h), result);
+    }
+
+    fn cache_key(op: BddOp, left: BddPointer, right: BddPointer) -> (BddPointer, BddPointer) {
+        // (f op g) and (g op f) share one entry for commutative operations
+        if op.is_commutative() && left.0 > right.0 { (right, left) } else { (left, right) }
+    }
+
+    /// Register the root of a new handle, so that its nodes survive garbage collection.
+    /// The terminals never move, so their roots are not registered.
+    pub fn register_root(&self, root: BddPointer) -> Arc<BddRoot> {
+        let cell = Arc::new(BddRoot::new(root));
+        if !root.is_terminal() {
+            let mut roots = self.roots.lock().unwrap();
+            // forget the dropped handles before the vector has to grow,
+            // so the registry stays proportional to the live handles
+            if roots.len() == roots.capacity() {
+                roots.retain(|root| root.strong_count() > 0);
+            }
+            roots.push(Arc::downgrade(&cell));
+        }
+        cell
+    }
+
+    /// The number of nodes in the arena at which garbage is collected.
+    pub fn gc_threshold(&self) -> usize { self.gc_threshold }
+
+    /// Set the number of nodes in the arena at which garbage is collected.
+    /// `usize::MAX` turns garbage collection off.
+    pub fn set_gc_threshold(&mut self, threshold: usize) {
+        self.gc_threshold = threshold;
+    }
+
+    /// Collect garbage if the arena has grown past the threshold. If most of the
+    /// nodes are still alive afterwards, the threshold is doubled, so a big live
+    /// diagram is not scanned again after every operation.
+    ///
+    /// Only call this when no pointer outside of a handle is in use.
+    pub fn collect_garbage_if_needed(&mut self) {
+        if self.nodes.len() >= self.gc_threshold {
+            self.collect_garbage();
+            if self.nodes.len() > self.gc_threshold / 2 {
+                self.gc_threshold = self.gc_threshold.saturating_mul(2);
+            }
+        }
+    }
+
+    /// Drop all the nodes that are not reachable from the root of a live handle and
+    /// return how many were dropped. The remaining nodes are compacted in one sweep,
+    /// which keeps them in bottom-up order, and the roots of the handles are moved
+    /// along. As the pointers change, the computed tables are cleared.
+    ///
+    /// Only call this when no pointer outside of a handle is in use.
+    pub fn collect_garbage(&mut self) -> usize {
+        let live = self.live_nodes();
+        let order: Vec<usize> = (0..self.nodes.len()).filter(|idx| live[*idx]).collect();
+        self.compact(order)
+    }
+
+    /// Like `collect_garbage`, but the nodes are laid out level by level, from the
+    /// last level up. After the levels of the variables changed, this restores the
+    /// bottom-up order of the arena.
+    pub(crate) fn collect_garbage_by_level<F: Fn(BddVar) -> usize>(&mut self, level: F) -> usize {
+        let live = self.live_nodes();
+        let mut order: Vec<usize> = (2..self.nodes.len()).filter(|idx| live[*idx]).collect();
+        order.sort_by_key(|idx| (std::cmp::Reverse(level(self.nodes[*idx].var)), *idx));
+        order.splice(0..0, [0, 1]);
+        self.compact(order)
+    }
+
+    /// Mark the nodes that are reachable from the root of a live handle.
+    fn live_nodes(&self) -> Vec<bool> {
+        let mut live = vec![false; self.nodes.len()];
+        live[0] = true;
+        live[1] = true;
+        let mut stack: Vec<usize> = self.live_roots().iter().map(|root| root.get().to_index()).collect();
+        while let Some(idx) = stack.pop() {
+            if live[idx] {
+                continue;
+            }
+            live[idx] = true;
+            stack.push(self.nodes[idx].low.to_index());
+            stack.push(self.nodes[idx].high.to_index());
+        }
+        live
+    }
+
+    fn live_roots(&self) -> Vec<Arc<BddRoot>> {
+        let mut roots = self.roots.lock().unwrap();
+        roots.retain(|root| root.strong_count() > 0);
+        roots.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Keep only the nodes in `order`, moved to the position they have in it.
+    /// The children of a node have to come before the node itself.
+    fn compact(&mut self, order: Vec<usize>) -> usize {
+        let mut new_index: Vec<u32> = vec![0; self.nodes.len()];
+        let mut nodes = Vec::with_capacity(order.len());
+        for idx in order {
+            let mut node = self.nodes[idx];
+            if idx >= 2 {
+                node.low = BddManager::moved(&new_index, node.low);
+                node.high = BddManager::moved(&new_index, node.high);
+            }
+            new_index[idx] = nodes.len() as u32;
+            nodes.push(node);
+        }
+        let collected = self.nodes.len() - nodes.len();
+        self.nodes = nodes;
+
+        for root in self.live_roots() {
+            root.set(BddManager::moved(&new_index, root.get()));
+        }
+        self.unique_table = self.nodes.iter().enumerate().skip(2)
+            .map(|(idx, node)| (*node, BddPointer::new(idx)))
+            .collect();
+        self.computed_table.clear();
+        self.ite_table.clear();
+        collected
+    }
+
+    fn moved(new_index: &[u32], ptr: BddPointer) -> BddPointer {
+        BddPointer(new_index[ptr.to_index()]).complement_if(ptr.is_complemented())
+    }
+
+    /// Count the references to every node, from its parents and from the roots of
+    /// the live handles, and list the live nodes of every variable. Nodes that are
+    /// not reachable from a live root are dead, so they are neither listed nor counted.
+    pub(crate) fn live_node_table(&self) -> LiveNodeTable {
+        let live = self.live_nodes();
+        let mut table = LiveNodeTable { refs: vec![0; self.nodes.len()], by_var: HashMap::new(), count: 2 };
+        for root in self.live_roots() {
+            table.refs[root.get().to_index()] += 1;
+        }
+        for idx in (2..self.nodes.len()).filter(|idx| live[*idx]) {
+            let node = self.nodes[idx];
+            table.refs[node.low.to_index()] += 1;
+            table.refs[node.high.to_index()] += 1;
+            table.by_var.entry(node.var).or_default().push(idx);
+            table.count += 1;
+        }
+        table
+    }
+
+    /// Swap the variable `upper` with the variable `lower` on the level right
+    /// below it. Every live node of `upper` which depends on `lower` is rewritten
+    /// in place into a node of `lower` with new children of `upper`, so every
+    /// pointer still stands for the same function. The new children are added
+    /// at the end of the arena, so it is no longer ordered bottom-up until it is
+    /// compacted by level.
+    ///
+    /// Only the nodes of the two variables are visited: the references in `table`
+    /// are updated along, so the nodes of `lower` that lose their last parent
+    /// are known to be dead without scanning the arena.
+    pub(crate) fn swap_adjacent_vars(&mut self, upper: BddVar, lower: BddVar, table: &mut LiveNodeTable) {
+        let upper_nodes = table.take_live(upper);
+        // the cofactors of a pointer by the lower variable
+        let cofactors = |manager: &BddManager, ptr: BddPointer| {
+            if !ptr.is_terminal() && manager.var_of_ptr(ptr) == lower {
+                (manager.low_node_ptr(ptr), manager.high_node_ptr(ptr))
+            } else {
+                (ptr, ptr)
+            }
+        };
+        for idx in upper_nodes {
+            let node = self.nodes[idx];
+            let (f00, f01) = cofactors(self, node.low);
+            let (f10, f11) = cofactors(self, node.high);
+            if (f00, f01) == (node.low, node.low) && (f10, f11) == (node.high, node.high) {
+                // the node does not depend on the lower variable
+                table.by_var.entry(upper).or_default().push(idx);
+                continue;
+            }
+            self.unique_table.remove(&node);
+            let low = self.mk_node(upper, f00, f10);
+            let high = self.mk_node(upper, f01, f11);
+            // f11 is a regular high edge, so the new node is stored as it is
+            debug_assert!(!high.is_complemented() && !high.is_zero());
+            let swapped = BddNode::mk_node(lower, low, high);
+            self.nodes[idx] = swapped;
+            self.unique_table.insert(swapped, BddPointer::new(idx));
+            table.by_var.entry(lower).or_default().push(idx);
+            // reference the new children before the old ones are released,
+            // so the nodes below the two levels never drop to zero references
+            table.reference(self, low);
+            table.reference(self, high);
+            table.release(self, node.low);
+            table.release(self, node.high);
+        }
+    }
+
+    /// Reorder the variables with `method` once a `Bdd` being built grows past
+    /// `threshold` nodes.
+    pub fn enable_reordering(&mut self, method: ReorderingMethod, threshold: usize) {
+        self.reordering = Some(method);
+        self.reorder_threshold = threshold;
+    }
+
+    pub fn disable_reordering(&mut self) {
+        self.reordering = None;
+    }
+
+    /// The method to reorder the variables with, if reordering is enabled
+    /// and a `Bdd` of `size` nodes has grown past the threshold.
+    pub fn reordering_due(&self, size: usize) -> Option<ReorderingMethod> {
+        self.reordering.filter(|_| size > self.reorder_threshold)
+    }
+
+    /// Raise the threshold after a reordering to twice the size that was reached,
+    /// so a diagram which is big under any order is not reordered over and over.
+    pub(crate) fn reordered(&mut self, size: usize) {
+        self.reorder_threshold = self.reorder_threshold.max(size.saturating_mul(2));
+    }
+
+    /// Over-approximate a `Bdd` being built once it grows past `limit` nodes,
+    /// until it is down to `target` nodes, with the approximation strategy.
+    ///
+    /// *Panics*:
+    ///  - If the target is bigger than the limit.
+    pub fn enable_approximation(&mut self, limit: usize, target: usize) {
+        assert!(target <= limit, "The target size {} is bigger than the limit {}", target, limit);
+        self.approximation_budget = Some((limit, target));
+    }
+
+    pub fn disable_approximation(&mut self) {
+        self.approximation_budget = None;
+    }
+
+    /// The size to approximate down to, if approximation is enabled and
+    /// a `Bdd` of `size` nodes has grown past the limit.
+    pub fn approximation_due(&self, size: usize) -> Option<usize> {
+        self.approximation_budget.filter(|(limit, _)| size > *limit).map(|(_, target)| target)
+    }
+
+    /// Approximate with `strategy` instead of rounding up.
+    pub fn set_approximation_strategy(&mut self, strategy: Box<dyn ApproximationStrategy>) {
+        self.approximation_strategy = Arc::from(strategy);
+    }
+
+    pub fn approximation_strategy(&self) -> Arc<dyn ApproximationStrategy> {
+        self.approximation_strategy.clone()
+    }
+
+    /// The number of nodes in the arena, including the terminals.
+    pub fn size(&self) -> usize { self.nodes.len() }
+
+    /// Get the variable of a specific pointer.
+    pub fn var_of_ptr(&self, ptr: BddPointer) -> BddVar {
+        self.nodes[ptr.to_index()].var
+    }
+
+    /// The low child of a pointer. The complement tag of the pointer is
+    /// pushed down, so the child is the low cofactor of the function it denotes.
+    pub fn low_node_ptr(&self, ptr: BddPointer) -> BddPointer {
+        self.nodes[ptr.to_index()].low.complement_if(ptr.is_complemented())
+    }
+
+    /// The high child of a pointer, with the complement tag pushed down.
+    pub fn high_node_ptr(&self, ptr: BddPointer) -> BddPointer {
+        self.nodes[ptr.to_index()].high.complement_if(ptr.is_complemented())
+    }
+
+    /// The pointers of all the nodes reachable from `root`, including the
+    /// terminals, in increasing order (so children always come before parents).
+    /// A node reached both through a regular and a complemented edge is listed
+    /// twice, once for each function it stands for.
+    pub fn reachable(&self, root: BddPointer) -> Vec<BddPointer> {
+        let mut visited = std::collections::HashSet::new();
+        visited.insert(BddPointer::new_zero());
+        visited.insert(BddPointer::new_one());
+        let mut stack = vec![root];
+        while let Some(ptr) = stack.pop() {
+            if !visited.insert(ptr) {
+                continue;
+            }
+            stack.push(self.low_node_ptr(ptr));
+            stack.push(self.high_node_ptr(ptr));
+        }
+        let mut reachable: Vec<BddPointer> = visited.into_iter().collect();
+        reachable.sort_by_key(|ptr| (ptr.to_index(), ptr.is_complemented()));
+        reachable
+    }
+
+    /// The number of stored nodes reachable from `root`, including the terminals.
+    pub fn node_count(&self, root: BddPointer) -> usize {
+        let mut visited = vec![false; self.nodes.len()];
+        visited[0] = true;
+        visited[1] = true;
+        let mut count = 2;
+        let mut stack = vec![root.regular()];
+        while let Some(ptr) = stack.pop() {
+            if visited[ptr.to_index()] {
+                continue;
+            }
+            visited[ptr.to_index()] = true;
+            count += 1;
+            stack.push(self.nodes[ptr.to_index()].low.regular());
+            stack.push(self.nodes[ptr.to_index()].high.regular());
+        }
+        count
+    }
+
+    /// Copy the nodes reachable from `root` into a `DetachedBdd`. In-place rewrites
+    /// (like the approximation) work on such a detached copy, so nodes shared with
+    /// other `Bdd`s are never touched. A node used in both polarities is copied
+    /// twice, as the copy has no complement edges. The root must not be a terminal.
+    pub fn detach(&self, root: BddPointer) -> DetachedBdd {
+        let mut detached = DetachedBdd::new();
+        let mut renaming: HashMap<BddPointer, BddPointer> = HashMap::new();
+        renaming.insert(BddPointer::new_zero(), BddPointer::new_zero());
+        renaming.insert(BddPointer::new_one(), BddPointer::new_one());
+        for ptr in self.reachable(root).into_iter().skip(2) {
+            let low = renaming[&self.low_node_ptr(ptr)];
+            let high = renaming[&self.high_node_ptr(ptr)];
+            renaming.insert(ptr, BddPointer::new(detached.size()));
+            detached.push_node(BddNode::mk_node(self.var_of_ptr(ptr), low, high));
+        }
+        detached
+    }
+
+    /// Insert a detached diagram into this manager and return the pointer
+    /// of its root. The nodes are reduced and hash-consed again.
+    pub fn import(&mut self, detached: &DetachedBdd) -> BddPointer {
+        let mut renaming: Vec<BddPointer> = Vec::with_capacity(detached.size());
+        renaming.push(BddPointer::new_zero());
+        renaming.push(BddPointer::new_one());
+        for node in detached.nodes.iter().skip(2) {
+            let low = renaming[node.low.to_index()];
+            let high = renaming[node.high.to_index()];
+            renaming.push(self.mk_node(node.var, low, high));
+        }
+        renaming[detached.root_pointer().to_index()]
+    }
+
+    /// Insert a node as it is, without reducing or hash-consing it, so the
+    /// tests can build diagrams that break the invariants of the manager.
+    #[cfg(test)]
+    pub(crate) fn push_node(&mut self, node: BddNode) {
+        self.nodes.push(node);
+    }
+}
+
+/// A copy of the nodes of a single diagram, made by `BddManager::detach`. It is
+/// laid out bottom-up with the root as the last node and has no complement edges,
+/// so the in-place rewrites of the approximation only need to know about the
+/// terminals 0 and 1. As it is not shared, no other `Bdd` sees these rewrites.
+#[derive(Clone, Debug)]
+pub struct DetachedBdd {
+    nodes: Vec<BddNode>,
+}
+
+impl Default for DetachedBdd {
+    fn default() -> Self {
+        DetachedBdd::new()
+    }
+}
+
+impl DetachedBdd {
+
+    /// Create a new diagram holding only the terminal nodes 0 and 1, which is the true formula.
+    pub fn new() -> DetachedBdd {
+        let max_ptr = BddVar::new(i32::MAX);
+        DetachedBdd { nodes: vec![BddNode::mk_zero(max_ptr), BddNode::mk_one(max_ptr)] }
+    }
+
+    /// The number of nodes, including the terminals.
+    pub fn size(&self) -> usize { self.nodes.len() }
+
+    /// Get the variable of a specific pointer.
+    pub fn var_of_ptr(&self, ptr: BddPointer) -> BddVar {
+        self.nodes[ptr.to_index()].var
+    }
+
+    pub fn low_node_ptr(&self, ptr: BddPointer) -> BddPointer {
+        self.nodes[ptr.to_index()].low
+    }
+
+    pub fn high_node_ptr(&self, ptr: BddPointer) -> BddPointer {
+        self.nodes[ptr.to_index()].high
+    }
+
+    /// True if the diagram is exactly the true formula.
+    pub fn is_true(&self) -> bool { self.nodes.len() == 2 }
+
+    /// Get the pointer of the root node.
+    pub fn root_pointer(&self) -> BddPointer {
+        if self.is_true() {
+            BddPointer::new_one()
+        } else {
+            BddPointer::new(self.nodes.len() - 1)
+        }
+    }
+
+    pub fn indices(&self) -> Vec<BddPointer> {
+        (0..self.size()).map(BddPointer::new).collect()
+    }
+
+    /// Insert a node into the vector of nodes. Its children must already be in it.
+    pub fn push_node(&mut self, node: BddNode) {
+        self.nodes.push(node);
+    }
+
+    pub fn replace_low(&mut self, ptr: BddPointer, new_ptr: BddPointer) { self.nodes[ptr.to_index()].low = new_ptr }
+
+    pub fn replace_high(&mut self, ptr: BddPointer, new_ptr: BddPointer) { self.nodes[ptr.to_index()].high = new_ptr }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bdd_util::{BddPointer, BddVar};
+    use super::*;
+
+    #[test]
+    fn unique_table_shares_nodes() {
+        let mut manager = BddManager::new();
+        let a = manager.mk_node(BddVar(1), BddPointer::new_zero(), BddPointer::new_one());
+        let b = manager.mk_node(BddVar(1), BddPointer::new_zero(), BddPointer::new_one());
+
+        assert_eq!(a, b);
+        assert_eq!(manager.size(), 3);
+    }
+
+    #[test]
+    fn redundant_nodes_are_skipped() {
+        let mut manager = BddManager::new();
+        let a = manager.mk_node(BddVar(1), BddPointer::new_one(), BddPointer::new_one());
+
+        assert_eq!(a, BddPointer::new_one());
+        assert_eq!(manager.size(), 2);
+    }
+
+    #[test]
+    fn detach_and_import() {
+        let mut manager = BddManager::new();
+        let x2 = manager.mk_node(BddVar(2), BddPointer::new_zero(), BddPointer::new_one());
+        let _unrelated = manager.mk_node(BddVar(3), BddPointer::new_one(), BddPointer::new_zero());
+        let root = manager.mk_node(BddVar(1), x2, BddPointer::new_one());
+
+        let detached = manager.detach(root);
+        assert_eq!(detached.size(), 4);
+        assert_eq!(detached.root_pointer(), BddPointer::new(3));
+        assert_eq!(manager.import(&detached), root);
+    }
+
+    #[test]
+    fn complemented_high_edges_are_normalized() {
+        let mut manager = BddManager::new();
+        let x1 = manager.mk_node(BddVar(1), BddPointer::new_zero(), BddPointer::new_one());
+        let not_x1 = manager.mk_node(BddVar(1), BddPointer::new_one(), BddPointer::new_zero());
+
+        assert_eq!(not_x1, x1.complement());
+        assert_eq!(manager.size(), 3);
+        assert!(manager.low_node_ptr(not_x1).is_one());
+        assert!(manager.high_node_ptr(not_x1).is_zero());
+    }
+
+    #[test]
+    fn detach_expands_complement_edges() {
+        let mut manager = BddManager::new();
+        let x2 = manager.mk_node(BddVar(2), BddPointer::new_zero(), BddPointer::new_one());
+        // x1 ? x2 : !x2 shares the node of x2 in both polarities
+        let root = manager.mk_node(BddVar(1), x2.complement(), x2);
+        assert_eq!(manager.node_count(root), 4);
+
+        let detached = manager.detach(root);
+        assert_eq!(detached.size(), 5);
+        assert_eq!(manager.import(&detached), root);
+    }
+
+    #[test]
+    fn computed_table_is_lossy() {
+        let mut table = ComputedTable::new(1);
+        table.insert((BddOp::And, BddPointer(2), BddPointer(3)), BddPointer(4));
+        assert_eq!(table.get(&(BddOp::And, BddPointer(2), BddPointer(3))), Some(BddPointer(4)));
+        assert_eq!(table.get(&(BddOp::Or, BddPointer(2), BddPointer(3))), None);
+
+        // a single slot, so the new result overwrites the old one
+        table.insert((BddOp::Or, BddPointer(2), BddPointer(3)), BddPointer(5));
+        assert_eq!(table.get(&(BddOp::And, BddPointer(2), BddPointer(3))), None);
+        assert_eq!(table.get(&(BddOp::Or, BddPointer(2), BddPointer(3))), Some(BddPointer(5)));
+    }
+
+    #[test]
+    fn commutative_results_are_shared() {
+        let mut manager = BddManager::new();
+        manager.cache_result(BddOp::And, BddPointer(3), BddPointer(2), BddPointer(4));
+        assert_eq!(manager.cached(BddOp::And, BddPointer(2), BddPointer(3)), Some(BddPointer(4)));
+    }
+}
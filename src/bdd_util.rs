@@ -2,9 +2,14 @@ use std::cmp::Ordering;
 use std::cmp::Ordering::{Equal, Greater, Less};
 
 // Nodes are represented as numbers with 0 and 1 reserved for the terminal nodes.
+// The highest bit of a pointer to a non-terminal node is the complement tag: a
+// tagged pointer stands for the negation of the node it points to. The terminals
+// are never tagged, the negation of 0 is simply 1 and vice versa.
 #[derive(Clone, Debug, Eq, Copy, Hash)]
 pub struct BddPointer(pub u32);
 
+const COMPLEMENT_BIT: u32 = 1 << 31;
+
 impl BddPointer {
 
     pub fn new(index: usize) -> BddPointer {
@@ -24,7 +29,7 @@ impl BddPointer {
     pub fn is_one(&self) -> bool { self.0 == 1 }
 
     pub fn is_terminal(&self) -> bool {
-        self.0 < 2
+        self.to_index() < 2
     }
 
     pub fn rename(&mut self, new: u32) { self.0 = new; }
@@ -45,8 +50,35 @@ impl BddPointer {
         }
     }
 
+    /// The index of the node in the node vector, without the complement tag.
     pub fn to_index(self) -> usize {
-        self.0 as usize
+        (self.0 & !COMPLEMENT_BIT) as usize
+    }
+
+    /// True if the pointer carries the complement tag.
+    pub fn is_complemented(&self) -> bool {
+        self.0 & COMPLEMENT_BIT != 0
+    }
+
+    /// The pointer to the negated function. This is O(1): a terminal is
+    /// flipped, any other pointer gets its complement tag toggled.
+    pub fn complement(self) -> BddPointer {
+        if self.is_terminal() {
+            BddPointer((self.0 + 1) % 2)
+        } else {
+            BddPointer(self.0 ^ COMPLEMENT_BIT)
+        }
+    }
+
+    /// The pointer without the complement tag.
+    pub fn regular(self) -> BddPointer {
+        BddPointer(self.0 & !COMPLEMENT_BIT)
+    }
+
+    /// Complement the pointer if `negate` is set, that is push the complement
+    /// tag of a parent edge down to the child.
+    pub fn complement_if(self, negate: bool) -> BddPointer {
+        if negate { self.complement() } else { self }
     }
 
     pub fn flip_if_terminal(&mut self) {
@@ -58,7 +90,11 @@ impl BddPointer {
 
 impl std::fmt::Display for BddPointer {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        f.write_fmt(format_args!("{}", self.0))
+        if self.is_complemented() {
+            f.write_fmt(format_args!("!{}", self.to_index()))
+        } else {
+            f.write_fmt(format_args!("{}", self.0))
+        }
     }
 }
 
@@ -309,4 +345,16 @@ mod tests {
         assert!(!node1.is_one());
         assert!(!node1.is_zero());
     }
+
+    #[test]
+    fn bdd_pointer_complement() {
+        let ptr = BddPointer::new(5);
+
+        assert!(ptr.complement().is_complemented());
+        assert_eq!(ptr.complement().to_index(), 5);
+        assert_eq!(ptr.complement().complement(), ptr);
+        assert_eq!(ptr.complement().regular(), ptr);
+        assert_eq!(BddPointer::new_zero().complement(), BddPointer::new_one());
+        assert!(!BddPointer::new_one().complement().is_complemented());
+    }
 }
\ No newline at end of file
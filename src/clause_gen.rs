@@ -1,6 +1,7 @@
 use std::time::Instant;
 use crossbeam_channel::{Receiver, TryRecvError};
 use crate::bdd::Bdd;
+use crate::bdd_manager::BddManager;
 use crate::bdd_util::BddPointer;
 use crate::statistics::stats::Stats;
 use crate::GlucoseWrapper;
@@ -26,10 +27,7 @@ impl Bdd {
     /// to a lower layer of the BDD.
     pub fn send_learned_clauses(&self, on_going: bool, clause_database: &mut ClauseDatabase, solver_wrapper: GlucoseWrapper, stats: &mut Stats, receiver: Receiver<()>) {
         let start = Instant::now();
-        let zero = BddPointer::new_zero();
-
-        // Search the Bdd backwards starting from the zero pointer
-        for ptr in self.indices() {
+        for learned_clause in self.learned_clauses(on_going) {
             // check if the other thread has finished
             match receiver.try_recv() {
                 Ok(_) | Err(TryRecvError::Disconnected) => {
@@ -39,39 +37,59 @@ impl Bdd {
                 }
                 Err(TryRecvError::Empty) => {}
             }
+            // do the actual sharing
+            clause_database.send(learned_clause, solver_wrapper, stats);
+        }
+        stats.add_t_send(start.elapsed());
+    }
+
+    /// The learned clauses of all the paths to zero, see `build_learned_clause`.
+    ///
+    /// The manager stays locked while the clauses are built: garbage collection in the
+    /// operations of the build moves the nodes and would leave the indices stale. The
+    /// clauses are only sent once the lock is released, so the build is not blocked
+    /// while Glucose adds them.
+    fn learned_clauses(&self, on_going: bool) -> Vec<Vec<i32>> {
+        let zero = BddPointer::new_zero();
+        let mut learned_clauses = Vec::new();
+
+        // Search the Bdd backwards starting from the zero pointer. The indices list
+        // complemented pointers separately and their children are read with the tag
+        // pushed down, so an edge into a complemented 1 is found as an edge into 0.
+        let manager = self.manager().read().unwrap();
+        let indices = manager.reachable(self.root_pointer());
+        for ptr in indices.iter().copied() {
             if ptr.is_terminal() {
                 // skip the terminal nodes
                 continue;
             }
-            if self.low_node_ptr(ptr) == zero {
+            if manager.low_node_ptr(ptr) == zero {
                 // create a new learned clause for every path starting from the zero pointer
                 let mut learned_clause: Vec<i32> = Vec::new();
-                let var = self.var_of_ptr(ptr).0;
+                let var = manager.var_of_ptr(ptr).0;
                 learned_clause.push(var);
                 // Generate the path after connecting the zero pointer.
                 let mut path = Vec::new();
                 path.push(ptr);
 
-                // try sending the clause if it's valid
-                if let Some(valid_learned_clause) = self.build_learned_clause(learned_clause, path, on_going) {
-                    // do the actual sharing
-                    clause_database.send(valid_learned_clause, solver_wrapper, stats);
+                // keep the clause if it's valid
+                if let Some(valid_learned_clause) = self.build_learned_clause(&manager, &indices, learned_clause, path, on_going) {
+                    learned_clauses.push(valid_learned_clause);
                 }
             }
-            if self.high_node_ptr(ptr) == zero {
+            if manager.high_node_ptr(ptr) == zero {
                 let mut learned_clause: Vec<i32> = Vec::new();
-                let var = self.var_of_ptr(ptr).0;
+                let var = manager.var_of_ptr(ptr).0;
                 learned_clause.push(-var);
                 let mut path = Vec::new();
                 path.push(ptr);
 
-                if let Some(valid_learned_clause) = self.build_learned_clause(learned_clause, path, on_going) {
-                    // do the actual sharing
-                    clause_database.send(valid_learned_clause, solver_wrapper, stats);
+                if let Some(valid_learned_clause) = self.build_learned_clause(&manager, &indices, learned_clause, path, on_going) {
+                    learned_clauses.push(valid_learned_clause);
                 }
             }
         }
-        stats.add_t_send(start.elapsed());
+        learned_clauses
     }
 
     /// A BDD is used to capture the relationship between Boolean variables of (a part of) the SAT problem,
@@ -79,11 +97,15 @@ impl Bdd {
     /// A learned clause corresponding to this conflict is easily obtained by negating the literals that define the path.
     /// Since a BDD captures all paths to 0, i.e. all possible conflicts, the potential advantage is that multiple learned
     /// clauses can be generated and added to the SAT solver at the same time.
-    pub fn build_learned_clause(&self, mut learned_clause: Vec<i32>, mut path: Vec<BddPointer>,
+    ///
+    /// The `indices` of the Bdd are computed once by the caller and shared by all the clauses.
+    /// They are only valid while the caller holds the lock of the `manager`.
+    pub fn build_learned_clause(&self, manager: &BddManager, indices: &[BddPointer], mut learned_clause: Vec<i32>, mut path: Vec<BddPointer>,
                                 on_going: bool) -> Option<Vec<i32>> {
         // The acc is the first pointer in the path in the beginnings
         let mut acc = *path.get(0).unwrap();
-        for ptr in self.indices().into_iter().skip(acc.to_index()) {
+        let start = indices.partition_point(|ptr| ptr.to_index() < acc.to_index());
+        for ptr in indices[start..].iter().copied() {
             if ptr.is_terminal() {
                 // skip the terminal nodes
                 continue;
@@ -92,14 +114,14 @@ impl Bdd {
             // of a state is detected when an unsatisfied clause contains no variable corresponding
             // to a lower layer of the BDD. When this occurs, we choose one such
             // clause as a witness of the infeasibility of the corresponding node.
-            if self.low_node_ptr(ptr) == acc {
-                let var = self.var_of_ptr(ptr).0;
+            if manager.low_node_ptr(ptr) == acc {
+                let var = manager.var_of_ptr(ptr).0;
                 learned_clause.push(var);
                 acc = ptr;
                 path.push(ptr);
             }
-            if self.high_node_ptr(ptr) == acc {
-                let var = self.var_of_ptr(ptr).0;
+            if manager.high_node_ptr(ptr) == acc {
+                let var = manager.var_of_ptr(ptr).0;
                 learned_clause.push(-var);
                 // save the new pointer in the accumulator
                 acc = ptr;
@@ -122,82 +144,72 @@ impl Bdd {
 
     pub fn send_learned_clauses_to_assumptions(&self, on_going: bool, clause_database: &mut ClauseDatabase, solver_wrapper: GlucoseWrapper, stats: &mut Stats) {
         let started = Instant::now();
-
-        let zero = BddPointer::new_zero();
-
-        // Search the Bdd backwards starting from the zero pointer
-        for ptr in self.indices() {
-            if ptr.is_terminal() {
-                // skip the terminal nodes
-                continue;
-            }
-            if self.low_node_ptr(ptr) == zero {
-                let mut learned_clause: Vec<i32> = Vec::new();
-                let var = self.var_of_ptr(ptr).0;
-                learned_clause.push(var);
-                let mut path = Vec::new();
-                path.push(ptr);
-
-                // try sending the clause if it's valid
-                if let Some(valid_learned_clause) = self.build_learned_clause(learned_clause, path, on_going) {
-                    clause_database.send_assumptions(valid_learned_clause, solver_wrapper, stats);
-                }
-            }
-            if self.high_node_ptr(ptr) == zero {
-                let mut learned_clause: Vec<i32> = Vec::new();
-                let var = self.var_of_ptr(ptr).0;
-                learned_clause.push(-var);
-                let mut path = Vec::new();
-                path.push(ptr);
-
-                if let Some(valid_learned_clause) = self.build_learned_clause(learned_clause, path, on_going) {
-                    // do the actual sharing
-                    clause_database.send_assumptions(valid_learned_clause, solver_wrapper, stats);
-                }
-            }
+        for learned_clause in self.learned_clauses(on_going) {
+            clause_database.send_assumptions(learned_clause, solver_wrapper, stats);
         }
         stats.add_t_send(started.elapsed());
     }
 
-    pub fn send_learned_clauses_without_solver_just_for_testing(&self, on_going: bool, _clause_database: &mut ClauseDatabase, stats: &mut Stats) {
+    /// Build the learned clauses like `send_learned_clauses`, but return them instead of sending them.
+    pub fn send_learned_clauses_without_solver_just_for_testing(&self, on_going: bool, _clause_database: &mut ClauseDatabase, stats: &mut Stats) -> Vec<Vec<i32>> {
         let start = Instant::now();
-
-        let zero = BddPointer::new_zero();
-
-        // Search the Bdd backwards starting from the zero pointer
-        for ptr in self.indices() {
-            if ptr.is_terminal() {
-                // skip the terminal nodes
-                continue;
-            }
-            if self.low_node_ptr(ptr) == zero {
-                // create a new learned clause for every path starting from the zero pointer
-                let mut learned_clause: Vec<i32> = Vec::new();
-                let var = self.var_of_ptr(ptr).0;
-                learned_clause.push(var);
-                // Generate the path after connecting the zero pointer.
-                let mut path = Vec::new();
-                path.push(ptr);
-
-                // try sending the clause if it's valid
-                if let Some(_) = self.build_learned_clause(learned_clause, path, on_going) {
-                    // do the actual sharing
-                    // clause_database.send(valid_learned_clause, solver_wrapper, stats);
-                }
-            }
-            if self.high_node_ptr(ptr) == zero {
-                let mut learned_clause: Vec<i32> = Vec::new();
-                let var = self.var_of_ptr(ptr).0;
-                learned_clause.push(-var);
-                let mut path = Vec::new();
-                path.push(ptr);
-
-                if let Some(_) = self.build_learned_clause(learned_clause, path, on_going) {
-                    // do the actual sharing
-                    // clause_database.send(valid_learned_clause, solver_wrapper, stats);
+        let learned_clauses = self.learned_clauses(on_going);
+        stats.add_t_send(start.elapsed());
+        learned_clauses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::expr::bool_expr::Expr;
+    use crate::parser::parse::parse_dimacs;
+    use crate::variable_ordering::var_ordering::BddVarOrdering;
+    use super::*;
+
+    #[test]
+    fn garbage_collection_during_traversal() {
+        let dimacs = parse_dimacs("tests/test4.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let mut rng = StdRng::seed_from_u64(42);
+
+        // random clauses over the variables of the instance, so the Bdd has a few hundred nodes
+        let clauses: Vec<Vec<i32>> = (0..12)
+            .map(|_| (0..3).map(|_| dimacs.vars[rng.gen_range(0..dimacs.vars.len())] * if rng.gen_bool(0.5) { 1 } else { -1 }).collect())
+            .collect();
+        let mut bdd = Bdd::new_true(var_ordering.manager());
+        for clause in &clauses {
+            bdd = var_ordering.and(&bdd, &var_ordering.build(&mut Expr::parse_clause(clause)));
+        }
+        // the three variables on the last levels
+        let mut bottom: Vec<(usize, i32)> = var_ordering.0.iter()
+            .filter(|(var, _)| **var != i32::MAX)
+            .map(|(var, level)| (*level, *var))
+            .collect();
+        bottom.sort();
+        let bottom: Vec<i32> = bottom.iter().rev().take(3).map(|(_, var)| -var).collect();
+
+        std::thread::scope(|scope| {
+            let traversals = scope.spawn(|| {
+                for _ in 0..10 {
+                    let learned_clauses = bdd.send_learned_clauses_without_solver_just_for_testing(false, &mut ClauseDatabase::new(), &mut Stats::new());
+                    assert!(!learned_clauses.is_empty());
+                    for clause in learned_clauses {
+                        let clause_bdd = var_ordering.build(&mut Expr::parse_clause(&clause));
+                        assert!(var_ordering.imp(&bdd, &clause_bdd).is_true(), "{:?} is not implied by the Bdd", clause);
+                    }
                 }
+            });
+            // Move the nodes of the Bdd over and over: new nodes on the last levels
+            // are laid out below them, and then collected as garbage again.
+            while !traversals.is_finished() {
+                let garbage = var_ordering.build(&mut Expr::parse_clause(&bottom));
+                let mut manager = var_ordering.manager().write().unwrap();
+                manager.collect_garbage_by_level(|var| var_ordering.0[&var.0]);
+                drop(garbage);
+                manager.collect_garbage();
             }
-        }
-        stats.add_t_send(start.elapsed());
+        });
     }
-}
\ No newline at end of file
+}
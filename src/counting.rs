@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use rand::Rng;
+use crate::bdd::Bdd;
+use crate::bdd_manager::BddManager;
+use crate::bdd_util::BddPointer;
+use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+/// The number type a model count is computed in. Only the two operations
+/// needed by the bottom-up pass are required: adding the counts of the two
+/// children and doubling a count once for every level that a child skips.
+trait ModelCount: Clone {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn add(&self, other: &Self) -> Self;
+    /// Multiply the count by $2^{levels}$.
+    fn shift(&self, levels: usize) -> Self;
+}
+
+impl ModelCount for BigUint {
+    fn zero() -> Self { Zero::zero() }
+    fn one() -> Self { One::one() }
+    fn add(&self, other: &Self) -> Self { self + other }
+    fn shift(&self, levels: usize) -> Self { self << levels }
+}
+
+impl ModelCount for f64 {
+    fn zero() -> Self { 0.0 }
+    fn one() -> Self { 1.0 }
+    fn add(&self, other: &Self) -> Self { self + other }
+    fn shift(&self, levels: usize) -> Self { self * 2f64.powi(levels as i32) }
+}
+
+/// A count kept as its base 2 logarithm, so it never overflows.
+#[derive(Clone)]
+struct Log2Count(f64);
+
+impl ModelCount for Log2Count {
+    fn zero() -> Self { Log2Count(f64::NEG_INFINITY) }
+    fn one() -> Self { Log2Count(0.0) }
+    fn add(&self, other: &Self) -> Self {
+        // log2(2^a + 2^b) = max + log2(1 + 2^(min - max))
+        let (max, min) = if self.0 >= other.0 { (self.0, other.0) } else { (other.0, self.0) };
+        if min == f64::NEG_INFINITY {
+            Log2Count(max)
+        } else {
+            Log2Count(max + (1.0 + (min - max).exp2()).log2())
+        }
+    }
+    fn shift(&self, levels: usize) -> Self { Log2Count(self.0 + levels as f64) }
+}
+
+/// The number type a weighted model count is computed in. The weight of a
+/// model is the product of the weights of its literals, and the weighted
+/// count is the sum over all the models.
+trait WeightedCount: Clone {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn from_weight(weight: f64) -> Self;
+    /// The number whose base 2 logarithm is `log2`.
+    fn from_log2(log2: f64) -> Self;
+    fn add(&self, other: &Self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+    fn negate(&self) -> Self;
+}
+
+impl WeightedCount for f64 {
+    fn zero() -> Self { 0.0 }
+    fn one() -> Self { 1.0 }
+    fn from_weight(weight: f64) -> Self { weight }
+    fn from_log2(log2: f64) -> Self { log2.exp2() }
+    fn add(&self, other: &Self) -> Self { self + other }
+    fn mul(&self, other: &Self) -> Self { self * other }
+    fn negate(&self) -> Self { -self }
+}
+
+/// A weighted count kept as its natural logarithm, so that products of
+/// many small weights do not underflow.
+#[derive(Clone)]
+struct LnWeight(f64);
+
+impl WeightedCount for LnWeight {
+    fn zero() -> Self { LnWeight(f64::NEG_INFINITY) }
+    fn one() -> Self { LnWeight(0.0) }
+    fn from_weight(weight: f64) -> Self { LnWeight(weight.ln()) }
+    fn from_log2(log2: f64) -> Self { LnWeight(log2 * std::f64::consts::LN_2) }
+    fn add(&self, other: &Self) -> Self {
+        // ln(e^a + e^b) = max + ln(1 + e^(min - max))
+        let (max, min) = if self.0 >= other.0 { (self.0, other.0) } else { (other.0, self.0) };
+        if min == f64::NEG_INFINITY {
+            LnWeight(max)
+        } else {
+            LnWeight(max + (min - max).exp().ln_1p())
+        }
+    }
+    fn mul(&self, other: &Self) -> Self { LnWeight(self.0 + other.0) }
+    // the logarithm of a negative count is not a number
+    fn negate(&self) -> Self { LnWeight(f64::NAN) }
+}
+
+/// The product of the free weights `negative + positive` of a range of levels,
+/// looked up in constant time from prefix sums of their base 2 logarithms.
+/// As a logarithm cannot hold a zero or a sign, the free weights that are zero
+/// and the negative ones are counted on the side.
+struct SkippedLevels {
+    log2: Vec<f64>,
+    zeros: Vec<usize>,
+    negatives: Vec<usize>,
+}
+
+impl SkippedLevels {
+
+    fn new(free_weights: &[f64]) -> SkippedLevels {
+        let mut skipped = SkippedLevels { log2: vec![0.0], zeros: vec![0], negatives: vec![0] };
+        for weight in free_weights {
+            skipped.log2.push(skipped.log2.last().unwrap() + if *weight == 0.0 { 0.0 } else { weight.abs().log2() });
+            skipped.zeros.push(skipped.zeros.last().unwrap() + (*weight == 0.0) as usize);
+            skipped.negatives.push(skipped.negatives.last().unwrap() + (*weight < 0.0) as usize);
+        }
+        skipped
+    }
+
+    /// The product of the free weights of the levels `from..to`.
+    fn product<T: WeightedCount>(&self, from: usize, to: usize) -> T {
+        if self.zeros[to] > self.zeros[from] {
+            return T::zero();
+        }
+        let product = T::from_log2(self.log2[to] - self.log2[from]);
+        if (self.negatives[to] - self.negatives[from]) % 2 == 1 { product.negate() } else { product }
+    }
+}
+
+impl Bdd {
+
+    /// Count the satisfying assignments of the Bdd over `num_vars` variables.
+    /// A variable whose level is skipped by an edge is free on that edge and
+    /// doubles the count, and so does every variable that is not in the ordering.
+    ///
+    /// *Panics*:
+    ///  - If `num_vars` is smaller than the number of variables in the ordering.
+    pub fn sat_count(&self, ordering: &BddVarOrdering, num_vars: usize) -> BigUint {
+        self.count_models(ordering, num_vars)
+    }
+
+    /// Like `sat_count`, but the count is computed as a floating point number.
+    /// The result is infinite if the count is bigger than `f64::MAX`.
+    pub fn sat_count_f64(&self, ordering: &BddVarOrdering, num_vars: usize) -> f64 {
+        self.count_models(ordering, num_vars)
+    }
+
+    /// The base 2 logarithm of `sat_count`, computed in log-space so that it
+    /// never overflows. The result is negative infinity for the false Bdd.
+    pub fn sat_count_log2(&self, ordering: &BddVarOrdering, num_vars: usize) -> f64 {
+        self.count_models::<Log2Count>(ordering, num_vars).0
+    }
+
+    /// The weighted model count of the Bdd over the variables of the ordering.
+    /// `weights` maps a variable to the weights of its negative and positive
+    /// literal, a variable without weights has the weights `(1.0, 1.0)`.
+    /// With the weights of a probability distribution, e.g. `(1.0 - p, p)`,
+    /// the result is the probability that the Bdd is true.
+    pub fn weighted_count(&self, weights: &HashMap<i32, (f64, f64)>, ordering: &BddVarOrdering) -> f64 {
+        self.weighted_models(weights, ordering)
+    }
+
+    /// The natural logarithm of `weighted_count`, computed in log-space so that
+    /// it does not underflow. The result is negative infinity if the count is 0.
+    pub fn weighted_count_ln(&self, weights: &HashMap<i32, (f64, f64)>, ordering: &BddVarOrdering) -> f64 {
+        self.weighted_models::<LnWeight>(weights, ordering).0
+    }
+
+    /// Draw `n` satisfying assignments of the Bdd uniformly at random. The assignments
+    /// are full over the variables of the ordering. At every node the branch is picked
+    /// with a probability proportional to the number of models below it, and the
+    /// variables on skipped levels are free, so they are picked with a fair coin.
+    /// The false Bdd has no models, so no assignment is drawn.
+    pub fn sample<R: Rng>(&self, rng: &mut R, n: usize, ordering: &BddVarOrdering) -> Vec<HashMap<i32, bool>> {
+        if self.is_false() {
+            return Vec::new();
+        }
+        let manager = self.manager().read().unwrap();
+        let counts = self.node_counts::<Log2Count>(&manager, ordering);
+        // the variables of the ordering by level
+        let mut vars_by_level: Vec<(usize, i32)> = ordering.0.iter()
+            .filter(|(var, _)| **var != i32::MAX)
+            .map(|(var, level)| (*level, *var))
+            .collect();
+        vars_by_level.sort();
+
+        let mut samples = Vec::with_capacity(n);
+        for _ in 0..n {
+            let mut assignment: HashMap<i32, bool> = HashMap::with_capacity(vars_by_level.len());
+            let mut ptr = self.root_pointer();
+            let mut level = 0;
+            loop {
+                // the levels between the parent and this node are skipped
+                let ptr_level = ordering.level(&manager, ptr);
+                for (_, var) in &vars_by_level[level..ptr_level] {
+                    assignment.insert(*var, rng.gen_bool(0.5));
+                }
+                if ptr.is_terminal() {
+                    break;
+                }
+                let (low, high) = (manager.low_node_ptr(ptr), manager.high_node_ptr(ptr));
+                let low_count = counts[&low].shift(ordering.level(&manager, low) - ptr_level - 1);
+                let high_count = counts[&high].shift(ordering.level(&manager, high) - ptr_level - 1);
+                // high_count / (low_count + high_count) computed from the logarithms
+                let p_high = 1.0 / (1.0 + (low_count.0 - high_count.0).exp2());
+                let value = rng.gen_bool(p_high);
+                assignment.insert(manager.var_of_ptr(ptr).0, value);
+                ptr = if value { high } else { low };
+                level = ptr_level + 1;
+            }
+            samples.push(assignment);
+        }
+        samples
+    }
+
+    fn count_models<T: ModelCount>(&self, ordering: &BddVarOrdering, num_vars: usize) -> T {
+        // the terminals are on the level after the last variable
+        let levels = *ordering.0.get(&i32::MAX).unwrap();
+        assert!(num_vars >= levels, "The ordering has {} variables but only {} are counted.", levels, num_vars);
+
+        let manager = self.manager().read().unwrap();
+        let counts = self.node_counts::<T>(&manager, ordering);
+        let root = self.root_pointer();
+        counts[&root].shift(ordering.level(&manager, root) + num_vars - levels)
+    }
+
+    /// The number of models of every node of the Bdd, counted over the
+    /// variables from the level of the node down to the terminals.
+    fn node_counts<T: ModelCount>(&self, manager: &BddManager, ordering: &BddVarOrdering) -> HashMap<BddPointer, T> {
+        let mut counts: HashMap<BddPointer, T> = HashMap::new();
+        counts.insert(BddPointer::new_zero(), T::zero());
+        counts.insert(BddPointer::new_one(), T::one());
+
+        // The children come before their parents, so their counts are known.
+        // A complemented pointer is counted through its own children.
+        for ptr in manager.reachable(self.root_pointer()).into_iter().skip(2) {
+            let level = ordering.level(manager, ptr);
+            let (low, high) = (manager.low_node_ptr(ptr), manager.high_node_ptr(ptr));
+            let low_count = counts[&low].shift(ordering.level(manager, low) - level - 1);
+            let high_count = counts[&high].shift(ordering.level(manager, high) - level - 1);
+            counts.insert(ptr, low_count.add(&high_count));
+        }
+        counts
+    }
+
+    fn weighted_models<T: WeightedCount>(&self, weights: &HashMap<i32, (f64, f64)>, ordering: &BddVarOrdering) -> T {
+        let levels = *ordering.0.get(&i32::MAX).unwrap();
+        // the literal weights of the variable on every level
+        let mut level_weights = vec![(T::one(), T::one()); levels];
+        let mut free_weights = vec![2.0; levels];
+        for (var, level) in ordering.0.iter().filter(|(var, _)| **var != i32::MAX) {
+            if let Some((negative, positive)) = weights.get(var) {
+                level_weights[*level] = (T::from_weight(*negative), T::from_weight(*positive));
+                free_weights[*level] = negative + positive;
+            }
+        }
+        // a skipped level is free, so it contributes the weights of both literals
+        let skipped_levels = SkippedLevels::new(&free_weights);
+        let skipped = |from: usize, to: usize| skipped_levels.product::<T>(from, to);
+
+        let manager = self.manager().read().unwrap();
+        let mut counts: HashMap<BddPointer, T> = HashMap::new();
+        counts.insert(BddPointer::new_zero(), T::zero());
+        counts.insert(BddPointer::new_one(), T::one());
+        for ptr in manager.reachable(self.root_pointer()).into_iter().skip(2) {
+            let level = ordering.level(&manager, ptr);
+            let (low, high) = (manager.low_node_ptr(ptr), manager.high_node_ptr(ptr));
+            let (negative, positive) = &level_weights[level];
+            let low_count = negative.mul(&skipped(level + 1, ordering.level(&manager, low))).mul(&counts[&low]);
+            let high_count = positive.mul(&skipped(level + 1, ordering.level(&manager, high))).mul(&counts[&high]);
+            counts.insert(ptr, low_count.add(&high_count));
+        }
+        let root = self.root_pointer();
+        skipped(0, ordering.level(&manager, root)).mul(&counts[&root])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use num_bigint::BigUint;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::bdd::Bdd;
+    use crate::expr::bool_expr::Expr;
+    use crate::parser::parse::parse_dimacs;
+    use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+    #[test]
+    fn count_models() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let mut bdd = Bdd::new_true(var_ordering.manager());
+        for expr in Expr::parse_clauses(&dimacs.clauses).iter_mut() {
+            bdd = var_ordering.and(&bdd, &var_ordering.build(expr));
+        }
+        // count the models of the clauses by enumerating all the assignments
+        let mut expected = 0u32;
+        for bits in 0..(1 << 5) {
+            let assignment: HashMap<i32, bool> = (1..6).map(|var| (var, bits & (1 << (var - 1)) != 0)).collect();
+            if dimacs.clauses.iter().all(|clause| Expr::parse_clause(clause).set_vars_and_solve(&assignment) == Some(true)) {
+                expected += 1;
+            }
+        }
+
+        assert_eq!(bdd.sat_count(&var_ordering, 5), BigUint::from(expected));
+        assert_eq!(bdd.negate().sat_count(&var_ordering, 5), BigUint::from(32 - expected));
+        assert_eq!(bdd.sat_count(&var_ordering, 7), BigUint::from(4 * expected));
+        assert_eq!(bdd.sat_count_f64(&var_ordering, 5), expected as f64);
+        assert!((bdd.sat_count_log2(&var_ordering, 5) - (expected as f64).log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn count_skipped_levels() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let x5 = var_ordering.build(&mut Expr::Var(5));
+        let manager = var_ordering.manager();
+        assert_eq!(x5.sat_count(&var_ordering, 5), BigUint::from(16u32));
+        assert_eq!(Bdd::new_true(manager).sat_count(&var_ordering, 5), BigUint::from(32u32));
+        assert_eq!(Bdd::new_false(manager).sat_count(&var_ordering, 5), BigUint::from(0u32));
+        assert_eq!(Bdd::new_false(manager).sat_count_log2(&var_ordering, 5), f64::NEG_INFINITY);
+        assert_eq!(Bdd::new_true(manager).sat_count(&var_ordering, 200), BigUint::from(1u32) << 200);
+        assert_eq!(Bdd::new_true(manager).sat_count_log2(&var_ordering, 2000), 2000.0);
+    }
+
+    #[test]
+    fn sample_uniformly() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let mut rng = StdRng::seed_from_u64(42);
+
+        // 24 models over the 5 variables, the variables 3, 4 and 5 are skipped
+        let f = var_ordering.build(&mut Expr::parse_clause(&vec![1, -2]));
+        let samples = f.sample(&mut rng, 24000, &var_ordering);
+        let mut frequencies: HashMap<Vec<bool>, usize> = HashMap::new();
+        for sample in &samples {
+            assert_eq!(sample.len(), 5);
+            assert_eq!(Expr::parse_clause(&vec![1, -2]).set_vars_and_solve(sample), Some(true));
+            *frequencies.entry((1..6).map(|var| sample[&var]).collect()).or_insert(0) += 1;
+        }
+        assert_eq!(frequencies.len(), 24);
+        // every model is expected 1000 times
+        assert!(frequencies.values().all(|freq| *freq > 800 && *freq < 1200));
+
+        assert!(Bdd::new_false(var_ordering.manager()).sample(&mut rng, 10, &var_ordering).is_empty());
+    }
+
+    #[test]
+    fn weighted_count_skipped_levels() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let x5 = var_ordering.build(&mut Expr::Var(5));
+        let one = Bdd::new_true(var_ordering.manager());
+
+        let mut weights: HashMap<i32, (f64, f64)> = (1..6).map(|var| (var, (0.5, 0.5))).collect();
+        assert_eq!(one.weighted_count(&weights, &var_ordering), 1.0);
+        assert_eq!(x5.weighted_count(&weights, &var_ordering), 0.5);
+        // a free weight of zero on a skipped level
+        weights.insert(2, (0.0, 0.0));
+        assert_eq!(one.weighted_count(&weights, &var_ordering), 0.0);
+        assert_eq!(x5.weighted_count_ln(&weights, &var_ordering), f64::NEG_INFINITY);
+        // a negative free weight on a skipped level
+        weights.insert(2, (-1.0, 0.5));
+        assert!((one.weighted_count(&weights, &var_ordering) + 0.5).abs() < 1e-12);
+        assert!((x5.weighted_count(&weights, &var_ordering) + 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn weighted_count() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let weights: HashMap<i32, (f64, f64)> = (1..6).map(|var| (var, (1.0 - 0.1 * var as f64, 0.1 * var as f64))).collect();
+
+        let mut bdd = Bdd::new_true(var_ordering.manager());
+        for expr in Expr::parse_clauses(&dimacs.clauses).iter_mut() {
+            bdd = var_ordering.and(&bdd, &var_ordering.build(expr));
+        }
+        // sum the weights of the models by enumerating all the assignments
+        let mut expected = 0.0;
+        for bits in 0..(1 << 5) {
+            let assignment: HashMap<i32, bool> = (1..6).map(|var| (var, bits & (1 << (var - 1)) != 0)).collect();
+            if dimacs.clauses.iter().all(|clause| Expr::parse_clause(clause).set_vars_and_solve(&assignment) == Some(true)) {
+                expected += assignment.iter()
+                    .map(|(var, value)| if *value { weights[var].1 } else { weights[var].0 })
+                    .product::<f64>();
+            }
+        }
+
+        assert!((bdd.weighted_count(&weights, &var_ordering) - expected).abs() < 1e-9);
+        assert!((bdd.negate().weighted_count(&weights, &var_ordering) - (1.0 - expected)).abs() < 1e-9);
+        assert!((bdd.weighted_count_ln(&weights, &var_ordering) - expected.ln()).abs() < 1e-9);
+        // without weights the weighted count is the model count
+        assert_eq!(bdd.weighted_count(&HashMap::new(), &var_ordering), bdd.sat_count_f64(&var_ordering, 5));
+        assert_eq!(Bdd::new_false(var_ordering.manager()).weighted_count_ln(&weights, &var_ordering), f64::NEG_INFINITY);
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use crate::bdd::Bdd;
+use crate::bdd_manager::DetachedBdd;
+use crate::bdd_util::BddPointer;
+
+/// A lazy iterator over the satisfying cubes of a `Bdd`, that is over its paths
+/// to 1. Each cube assigns only the variables on its path, the other variables
+/// are free. The cubes are disjoint, so together they cover every model once.
+///
+/// The iterator works on a detached copy of the diagram, so it holds no lock
+/// on the manager while it is alive.
+pub struct BddCubes {
+    nodes: DetachedBdd,
+    // the pointers still to visit, with the cube of the path leading to them
+    stack: Vec<(BddPointer, Vec<(i32, bool)>)>,
+}
+
+impl Iterator for BddCubes {
+    type Item = HashMap<i32, bool>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((ptr, cube)) = self.stack.pop() {
+            if ptr.is_zero() {
+                continue;
+            }
+            if ptr.is_one() {
+                return Some(cube.into_iter().collect());
+            }
+            let var = self.nodes.var_of_ptr(ptr).0;
+            let mut high_cube = cube.clone();
+            high_cube.push((var, true));
+            let mut low_cube = cube;
+            low_cube.push((var, false));
+            // the low branch is visited first
+            self.stack.push((self.nodes.high_node_ptr(ptr), high_cube));
+            self.stack.push((self.nodes.low_node_ptr(ptr), low_cube));
+        }
+        None
+    }
+}
+
+/// A lazy iterator over the full satisfying assignments of a `Bdd` over a given
+/// set of variables. Every cube is expanded into all the assignments of the
+/// variables it leaves free.
+pub struct BddModels {
+    cubes: BddCubes,
+    vars: Vec<i32>,
+    // the current cube, its free variables and the values they have in the next model
+    cube: HashMap<i32, bool>,
+    free_vars: Vec<i32>,
+    free_values: Option<Vec<bool>>,
+}
+
+impl Iterator for BddModels {
+    type Item = HashMap<i32, bool>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(values) = self.free_values.as_mut() {
+                let mut model = self.cube.clone();
+                for (var, value) in self.free_vars.iter().zip(values.iter()) {
+                    model.insert(*var, *value);
+                }
+                // count up the values of the free variables like a binary number
+                match values.iter().position(|value| !value) {
+                    Some(pos) => {
+                        values[..pos].iter_mut().for_each(|value| *value = false);
+                        values[pos] = true;
+                    }
+                    None => self.free_values = None,
+                }
+                return Some(model);
+            }
+            let cube = self.cubes.next()?;
+            self.free_vars = self.vars.iter().filter(|var| !cube.contains_key(var)).cloned().collect();
+            self.free_values = Some(vec![false; self.free_vars.len()]);
+            self.cube = cube;
+        }
+    }
+}
+
+impl Bdd {
+
+    /// Iterate over all the satisfying cubes of the Bdd. A cube is a partial
+    /// assignment: the variables it does not assign can take any value.
+    pub fn cubes(&self) -> BddCubes {
+        let mut stack = Vec::new();
+        let nodes = if self.is_true() || self.is_false() {
+            // there is only the empty cube or no cube at all
+            stack.push((self.root_pointer(), Vec::new()));
+            DetachedBdd::new()
+        } else {
+            let nodes = self.manager().read().unwrap().detach(self.root_pointer());
+            stack.push((nodes.root_pointer(), Vec::new()));
+            nodes
+        };
+        BddCubes { nodes, stack }
+    }
+
+    /// Iterate over all the satisfying assignments of the Bdd over the given
+    /// variables. The variables should contain the support of the Bdd, otherwise
+    /// the models are only full on `vars` and the variables of their cube.
+    pub fn models(&self, vars: &[i32]) -> BddModels {
+        BddModels {
+            cubes: self.cubes(),
+            vars: vars.to_vec(),
+            cube: HashMap::new(),
+            free_vars: Vec::new(),
+            free_values: None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::bdd::Bdd;
+    use crate::expr::bool_expr::Expr;
+    use crate::parser::parse::parse_dimacs;
+    use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+    #[test]
+    fn enumerate_cubes() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let f = var_ordering.build(&mut Expr::parse_clause(&vec![1, -2]));
+        let cubes: Vec<_> = f.cubes().collect();
+        // x1 is on the top level, so the cubes are {-1,-2} and {1}
+        assert_eq!(cubes.len(), 2);
+        for cube in &cubes {
+            assert_eq!(Expr::parse_clause(&vec![1, -2]).set_vars_and_solve(cube), Some(true));
+        }
+
+        assert_eq!(Bdd::new_true(var_ordering.manager()).cubes().count(), 1);
+        assert_eq!(Bdd::new_false(var_ordering.manager()).cubes().count(), 0);
+    }
+
+    #[test]
+    fn enumerate_models() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let mut bdd = Bdd::new_true(var_ordering.manager());
+        for expr in Expr::parse_clauses(&dimacs.clauses).iter_mut() {
+            bdd = var_ordering.and(&bdd, &var_ordering.build(expr));
+        }
+        let models: Vec<_> = bdd.models(&dimacs.vars).collect();
+        let distinct: HashSet<Vec<bool>> = models.iter()
+            .map(|model| (1..6).map(|var| model[&var]).collect())
+            .collect();
+
+        assert_eq!(models.len(), distinct.len());
+        assert_eq!(num_bigint::BigUint::from(models.len()), bdd.sat_count(&var_ordering, 5));
+        for model in &models {
+            for clause in &dimacs.clauses {
+                assert_eq!(Expr::parse_clause(clause).set_vars_and_solve(model), Some(true));
+            }
+        }
+        assert_eq!(Bdd::new_true(var_ordering.manager()).models(&vec![1, 2, 3]).count(), 8);
+    }
+}
@@ -222,11 +222,102 @@ pub fn or(l: Option<bool>, r: Option<bool>) -> Option<bool> {
     }
 }
 
+/// Partial operator function corresponding to $x \oplus y$.
+pub fn xor(l: Option<bool>, r: Option<bool>) -> Option<bool> {
+    match (l, r) {
+        (Some(l), Some(r)) => Some(l ^ r),
+        _ => None,
+    }
+}
+
+/// Partial operator function corresponding to $x \Rightarrow y$.
+pub fn imp(l: Option<bool>, r: Option<bool>) -> Option<bool> {
+    match (l, r) {
+        (Some(true), Some(false)) => Some(false),
+        (Some(false), _) => Some(true),
+        (_, Some(true)) => Some(true),
+        _ => None,
+    }
+}
+
+/// Partial operator function corresponding to $x \Leftrightarrow y$.
+pub fn iff(l: Option<bool>, r: Option<bool>) -> Option<bool> {
+    match (l, r) {
+        (Some(l), Some(r)) => Some(l == r),
+        _ => None,
+    }
+}
+
+/// Partial operator function corresponding to $\neg(x \land y)$.
+pub fn nand(l: Option<bool>, r: Option<bool>) -> Option<bool> {
+    match (l, r) {
+        (Some(true), Some(true)) => Some(false),
+        (Some(false), _) => Some(true),
+        (_, Some(false)) => Some(true),
+        _ => None,
+    }
+}
+
+/// Partial operator function corresponding to $\neg(x \lor y)$.
+pub fn nor(l: Option<bool>, r: Option<bool>) -> Option<bool> {
+    match (l, r) {
+        (Some(false), Some(false)) => Some(true),
+        (Some(true), _) => Some(false),
+        (_, Some(true)) => Some(false),
+        _ => None,
+    }
+}
+
+/// Partial operator function corresponding to $x \land \neg y$.
+pub fn and_not(l: Option<bool>, r: Option<bool>) -> Option<bool> {
+    match (l, r) {
+        (Some(true), Some(false)) => Some(true),
+        (Some(false), _) => Some(false),
+        (_, Some(true)) => Some(false),
+        _ => None,
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
+    use crate::expr::bool_expr;
     use crate::expr::bool_expr::Expr;
 
+    #[test]
+    pub fn test_partial_operators() {
+        let values = [None, Some(false), Some(true)];
+        let total: [(fn(Option<bool>, Option<bool>) -> Option<bool>, fn(bool, bool) -> bool); 8] = [
+            (bool_expr::and, |l, r| l && r),
+            (bool_expr::or, |l, r| l || r),
+            (bool_expr::xor, |l, r| l ^ r),
+            (bool_expr::imp, |l, r| !l || r),
+            (bool_expr::iff, |l, r| l == r),
+            (bool_expr::nand, |l, r| !(l && r)),
+            (bool_expr::nor, |l, r| !(l || r)),
+            (bool_expr::and_not, |l, r| l && !r),
+        ];
+        for (partial, op) in total {
+            for l in values {
+                for r in values {
+                    match partial(l, r) {
+                        // a short-circuit must agree with every completion of the operands
+                        Some(res) => {
+                            for lv in [false, true] {
+                                for rv in [false, true] {
+                                    if l.unwrap_or(lv) == lv && r.unwrap_or(rv) == rv {
+                                        assert_eq!(res, op(lv, rv));
+                                    }
+                                }
+                            }
+                        }
+                        None => assert!(l.is_none() || r.is_none()),
+                    }
+                }
+            }
+        }
+    }
+
     #[test]
     pub fn test_parse_var1() {
         let var1 = -1;
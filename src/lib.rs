@@ -4,10 +4,21 @@ pub mod parser {
 pub mod variable_ordering {
     pub mod var_ordering_builder;
     pub mod var_ordering;
+    pub mod reordering;
+    pub mod ordering_strategy;
+    pub mod ordering_metrics;
+    pub mod clause_schedule;
+    pub mod bucket_elimination;
 }
 pub mod bdd;
+pub mod bdd_manager;
 pub mod bdd_util;
 pub mod approx;
+pub mod approximation_strategy;
+pub mod counting;
+pub mod enumeration;
+pub mod quantification;
+pub mod solver;
 mod clause_gen;
 
 pub mod statistics {
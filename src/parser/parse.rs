@@ -15,6 +15,8 @@ pub struct Dimacs {
     pub vars: Vec<i32>,
     pub vars_scores: HashMap<i32, f64>,
     pub clauses: Vec<Vec<i32>>,
+    /// The variable ordering given by `c order ...` comments in the header, top to bottom.
+    pub order: Option<Vec<i32>>,
 }
 
 pub fn parse_dimacs(path: &str) -> Dimacs {
@@ -28,6 +30,7 @@ pub fn parse_dimacs(path: &str) -> Dimacs {
     let mut nb_c = 0;
     let mut vars = Vec::new();
     let mut clauses = Vec::new();
+    let mut order: Option<Vec<i32>> = None;
 
     // this hashmap contains a variable and the arities of the clauses where
     // this variable is appearing.
@@ -37,6 +40,11 @@ pub fn parse_dimacs(path: &str) -> Dimacs {
         match pair.as_rule() {
             Rule::n => { nb_v = pair.as_str().parse().unwrap(); }
             Rule::m => { nb_c = pair.as_str().parse().unwrap(); }
+            Rule::order => {
+                // the ordering can be split over several comment lines
+                let order = order.get_or_insert_with(Vec::new);
+                order.extend(pair.into_inner().map(|var| var.as_str().parse::<i32>().unwrap()));
+            }
             Rule::clause => {
                 let mut clause: Vec<i32> = Vec::new();
                 let mut clause_vars: Vec<i32> = Vec::new();
@@ -73,6 +81,7 @@ pub fn parse_dimacs(path: &str) -> Dimacs {
         vars,
         vars_scores,
         clauses,
+        order,
     }
 }
 
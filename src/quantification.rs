@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+use crate::bdd::Bdd;
+use crate::bdd_manager::BddManager;
+use crate::bdd_util::BddPointer;
+use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+/// The set of variables to quantify, together with the deepest of their levels.
+/// Below this level there is nothing left to quantify.
+struct QuantifiedVars {
+    vars: HashSet<i32>,
+    last_level: Option<usize>,
+}
+
+impl QuantifiedVars {
+    fn new(ordering: &BddVarOrdering, vars: &[i32]) -> QuantifiedVars {
+        let vars: HashSet<i32> = vars.iter().cloned().collect();
+        let last_level = vars.iter().filter_map(|var| ordering.0.get(var)).max().cloned();
+        QuantifiedVars { vars, last_level }
+    }
+
+    fn contains(&self, var: i32) -> bool {
+        self.vars.contains(&var)
+    }
+
+    /// True if no quantified variable is on the given level or below it.
+    fn all_above(&self, level: usize) -> bool {
+        self.last_level.is_none_or(|last| level > last)
+    }
+}
+
+impl BddVarOrdering {
+
+    /// Create a `Bdd` corresponding to $\exists vars. \phi$, where $\phi$ is the given `Bdd`:
+    /// every variable of `vars` is eliminated by taking the disjunction of its two cofactors.
+    pub fn exists(&self, bdd: &Bdd, vars: &[i32]) -> Bdd {
+        let vars = QuantifiedVars::new(self, vars);
+        let mut manager = self.lock_for_operation();
+        let mut finished: HashMap<BddPointer, BddPointer> = HashMap::new();
+        let root = self.exists_rec(&mut manager, bdd.root_pointer(), &vars, &mut finished);
+        Bdd::from_locked(&self.1, &manager, root)
+    }
+
+    /// Create a `Bdd` corresponding to $\forall vars. \phi$, where $\phi$ is the given `Bdd`.
+    /// As $\forall x. \phi = \neg \exists x. \neg \phi$ this is the existential quantification
+    /// of the negation, which is free with complement edges.
+    pub fn forall(&self, bdd: &Bdd, vars: &[i32]) -> Bdd {
+        self.exists(&bdd.negate(), vars).negate()
+    }
+
+    /// Create a `Bdd` corresponding to $\exists vars. (\phi \land \psi)$, where $\phi$ and $\psi$
+    /// are the two given `Bdd`s. The conjunction and the quantification are done in one pass
+    /// (the relational product), so the full conjunction is never built.
+    pub fn and_exists(&self, left: &Bdd, right: &Bdd, vars: &[i32]) -> Bdd {
+        let vars = QuantifiedVars::new(self, vars);
+        let mut manager = self.lock_for_operation();
+        let mut finished_exists: HashMap<BddPointer, BddPointer> = HashMap::new();
+        let mut finished: HashMap<(BddPointer, BddPointer), BddPointer> = HashMap::new();
+        let root = self.and_exists_rec(&mut manager, left.root_pointer(), right.root_pointer(),
+                                       &vars, &mut finished_exists, &mut finished);
+        Bdd::from_locked(&self.1, &manager, root)
+    }
+
+    fn exists_rec(&self, manager: &mut BddManager, f: BddPointer, vars: &QuantifiedVars,
+                  finished: &mut HashMap<BddPointer, BddPointer>) -> BddPointer {
+        if f.is_terminal() || vars.all_above(self.level(manager, f)) {
+            return f;
+        }
+        if let Some(result) = finished.get(&f) {
+            return *result;
+        }
+        let var = manager.var_of_ptr(f);
+        let (f_low, f_high) = (manager.low_node_ptr(f), manager.high_node_ptr(f));
+        let low = self.exists_rec(manager, f_low, vars, finished);
+        let result = if vars.contains(var.0) {
+            if low.is_one() {
+                low
+            } else {
+                let high = self.exists_rec(manager, f_high, vars, finished);
+                // low or high
+                self.ite_rec(manager, low, BddPointer::new_one(), high)
+            }
+        } else {
+            let high = self.exists_rec(manager, f_high, vars, finished);
+            manager.mk_node(var, low, high)
+        };
+        finished.insert(f, result);
+        result
+    }
+
+    fn and_exists_rec(&self, manager: &mut BddManager, f: BddPointer, g: BddPointer, vars: &QuantifiedVars,
+                      finished_exists: &mut HashMap<BddPointer, BddPointer>,
+                      finished: &mut HashMap<(BddPointer, BddPointer), BddPointer>) -> BddPointer {
+        // terminal cases
+        if f.is_zero() || g.is_zero() || f == g.complement() {
+            return BddPointer::new_zero();
+        }
+        if f.is_one() || f == g {
+            return self.exists_rec(manager, g, vars, finished_exists);
+        }
+        if g.is_one() {
+            return self.exists_rec(manager, f, vars, finished_exists);
+        }
+        // nothing left to quantify, so this is a plain conjunction
+        if vars.all_above(self.level(manager, f)) && vars.all_above(self.level(manager, g)) {
+            return self.ite_rec(manager, f, g, BddPointer::new_zero());
+        }
+        let key = if f.0 < g.0 { (f, g) } else { (g, f) };
+        if let Some(result) = finished.get(&key) {
+            return *result;
+        }
+
+        let (f_level, g_level) = (self.level(manager, f), self.level(manager, g));
+        let var = if f_level <= g_level { manager.var_of_ptr(f) } else { manager.var_of_ptr(g) };
+        let (f_low, f_high) = if f_level <= g_level {
+            (manager.low_node_ptr(f), manager.high_node_ptr(f))
+        } else {
+            (f, f)
+        };
+        let (g_low, g_high) = if g_level <= f_level {
+            (manager.low_node_ptr(g), manager.high_node_ptr(g))
+        } else {
+            (g, g)
+        };
+
+        let low = self.and_exists_rec(manager, f_low, g_low, vars, finished_exists, finished);
+        let result = if vars.contains(var.0) {
+            if low.is_one() {
+                low
+            } else {
+                let high = self.and_exists_rec(manager, f_high, g_high, vars, finished_exists, finished);
+                // low or high
+                self.ite_rec(manager, low, BddPointer::new_one(), high)
+            }
+        } else {
+            let high = self.and_exists_rec(manager, f_high, g_high, vars, finished_exists, finished);
+            manager.mk_node(var, low, high)
+        };
+        finished.insert(key, result);
+        result
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::expr::bool_expr::Expr;
+    use crate::parser::parse::parse_dimacs;
+    use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+    #[test]
+    fn quantification() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let f = var_ordering.build(&mut Expr::parse_clause(&vec![1, -2]));
+        let g = var_ordering.build(&mut Expr::parse_clause(&vec![2, 3]));
+        let x2 = var_ordering.build(&mut Expr::Var(2));
+        let f_and_g = var_ordering.and(&f, &g);
+
+        // f[2 := 0] or f[2 := 1]
+        let f_low = var_ordering.and(&f, &x2.negate());
+        let f_high = var_ordering.and(&f, &x2);
+        assert!(var_ordering.exists(&f, &vec![2]).is_true());
+        assert_eq!(var_ordering.exists(&f_high, &vec![2]), var_ordering.build(&mut Expr::Var(1)));
+        assert_eq!(var_ordering.exists(&f_low, &vec![2]), var_ordering.exists(&f_low, &vec![2, 4]));
+
+        // (1 or -2) and (2 or 3) projected on 1 and 3 is (1 or 3)
+        let expected = var_ordering.build(&mut Expr::parse_clause(&vec![1, 3]));
+        assert_eq!(var_ordering.exists(&f_and_g, &vec![2]), expected);
+        assert_eq!(var_ordering.and_exists(&f, &g, &vec![2]), expected);
+        assert_eq!(var_ordering.and_exists(&f, &g, &vec![1, 2, 3]), var_ordering.exists(&f_and_g, &vec![1, 2, 3]));
+
+        assert_eq!(var_ordering.forall(&f, &vec![2]), var_ordering.build(&mut Expr::Var(1)));
+        assert!(var_ordering.forall(&g, &vec![2, 3]).is_false());
+        assert_eq!(f_and_g.support(), vec![1, 2, 3]);
+    }
+}
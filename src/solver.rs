@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use crate::bdd::Bdd;
+use crate::expr::bool_expr::Expr;
+use crate::parser::parse::Dimacs;
+use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+/// The answer of the `BddSolver` for an instance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SolverResult {
+    /// The instance is satisfiable. The model assigns every variable from 1 to
+    /// the number of variables in the header and satisfies every clause.
+    Sat(HashMap<i32, bool>),
+    /// The instance is unsatisfiable.
+    Unsat,
+    /// The solver ran out of its node or time budget before deciding.
+    Unknown,
+    /// The model read off the Bdd does not satisfy this clause, so the Bdd is wrong.
+    InvalidModel(Vec<i32>),
+}
+
+/// Decide a DIMACS instance by building the Bdd of its clauses, one clause
+/// after the other. Without budgets the solver always decides the instance,
+/// but the Bdd can grow exponentially on the way.
+#[derive(Clone, Debug, Default)]
+pub struct BddSolver {
+    /// Give up as soon as the Bdd of the clauses conjoined so far has more nodes.
+    pub node_budget: Option<usize>,
+    /// Give up once the time is spent. The budget is checked between two
+    /// conjunctions, so a single large conjunction can overrun it.
+    pub time_budget: Option<Duration>,
+}
+
+impl BddSolver {
+
+    /// A solver without any budget.
+    pub fn new() -> BddSolver {
+        BddSolver { node_budget: None, time_budget: None }
+    }
+
+    /// Decide the instance, with the variable ordering of `BddVarOrdering::new`.
+    pub fn solve(&self, dimacs: &Dimacs) -> SolverResult {
+        let start = Instant::now();
+        let var_ordering = BddVarOrdering::new(dimacs);
+
+        let mut bdd = Bdd::new_true(var_ordering.manager());
+        for clause in &dimacs.clauses {
+            if self.time_budget.is_some_and(|budget| start.elapsed() >= budget) {
+                return SolverResult::Unknown;
+            }
+            let clause_bdd = var_ordering.build(&mut Expr::parse_clause(clause));
+            bdd = var_ordering.and(&bdd, &clause_bdd);
+            if bdd.is_false() {
+                return SolverResult::Unsat;
+            }
+            if self.node_budget.is_some_and(|budget| bdd.size() > budget) {
+                return SolverResult::Unknown;
+            }
+        }
+
+        // the path to one only sets the variables on it, the others are free
+        let mut model = bdd.solve(&dimacs.vars).unwrap();
+        for var in dimacs.vars.iter().copied().chain(1..=dimacs.nb_v as i32) {
+            model.entry(var).or_insert(false);
+        }
+        match unsatisfied_clause(&model, &dimacs.clauses) {
+            Some(clause) => SolverResult::InvalidModel(clause.clone()),
+            None => SolverResult::Sat(model),
+        }
+    }
+}
+
+/// The first clause that the model does not satisfy, if any.
+fn unsatisfied_clause<'a>(model: &HashMap<i32, bool>, clauses: &'a [Vec<i32>]) -> Option<&'a Vec<i32>> {
+    clauses.iter().find(|clause| !clause.iter().any(|lit| model[&lit.abs()] == (*lit > 0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse::parse_dimacs;
+    use super::*;
+
+    #[test]
+    fn sat_and_unsat() {
+        for path in ["tests/test3.cnf", "tests/test4.cnf", "tests/test5.cnf"] {
+            let dimacs = parse_dimacs(path);
+            let SolverResult::Sat(model) = BddSolver::new().solve(&dimacs) else {
+                panic!("{} is satisfiable", path)
+            };
+            assert_eq!(model.len(), dimacs.nb_v, "{}", path);
+        }
+        assert_eq!(BddSolver::new().solve(&parse_dimacs("tests/test6.cnf")), SolverResult::Unsat);
+    }
+
+    #[test]
+    fn out_of_budget() {
+        let dimacs = parse_dimacs("tests/test4.cnf");
+        let solver = BddSolver { node_budget: Some(2), time_budget: None };
+        assert_eq!(solver.solve(&dimacs), SolverResult::Unknown);
+        let solver = BddSolver { node_budget: None, time_budget: Some(Duration::ZERO) };
+        assert_eq!(solver.solve(&dimacs), SolverResult::Unknown);
+
+        // no Bdd over the three variables has more than seven nodes
+        let solver = BddSolver { node_budget: Some(7), time_budget: None };
+        assert_eq!(solver.solve(&parse_dimacs("tests/test6.cnf")), SolverResult::Unsat);
+    }
+
+    #[test]
+    fn models_are_checked() {
+        let clauses = vec![vec![1, 2], vec![-1, 2]];
+        let model = HashMap::from([(1, true), (2, false)]);
+        assert_eq!(unsatisfied_clause(&model, &clauses), Some(&vec![-1, 2]));
+        let model = HashMap::from([(1, true), (2, true)]);
+        assert_eq!(unsatisfied_clause(&model, &clauses), None);
+    }
+}
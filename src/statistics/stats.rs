@@ -16,6 +16,20 @@ pub struct Stats {
     t_send_learned_clauses: Vec<Duration>,
     t_approx: Vec<Duration>,
     bdd_size: Vec<usize>,
+    approximation_steps: Vec<ApproximationStep>,
+}
+
+/// One pass of an approximation: the edges it redirected, to a terminal, to the
+/// other child of the node or to the conjunction of its children, and the size of
+/// the `Bdd` before and after the pass.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApproximationStep {
+    /// The redirected edges, as the variable of the node and true if its high edge was redirected.
+    pub redirected: Vec<(i32, bool)>,
+    /// True if the pass over-approximated the Bdd, false if it under-approximated it.
+    pub over: bool,
+    pub size_before: usize,
+    pub size_after: usize,
 }
 
 impl Stats {
@@ -35,6 +49,7 @@ impl Stats {
             t_send_learned_clauses: Vec::new(),
             t_approx: Vec::new(),
             bdd_size: Vec::new(),
+            approximation_steps: Vec::new(),
         }
     }
 
@@ -82,6 +97,9 @@ impl Stats {
 
     pub fn add_bdd_size(&mut self, size: usize) { self.bdd_size.push(size); }
 
+    pub fn add_approximation_step(&mut self, step: ApproximationStep) { self.approximation_steps.push(step); }
+    pub fn approximation_steps(&self) -> &[ApproximationStep] { &self.approximation_steps }
+
     pub fn add_received_glucose(&mut self) { self.received_clauses_glucose += 1; }
     pub fn add_received_bdd(&mut self) { self.received_clauses_bdd += 1; }
     pub fn add_sent_glucose(&mut self) { self.sent_clauses_glucose += 1; }
@@ -113,6 +131,7 @@ impl std::fmt::Debug for Stats {
             write!(f, "{}: {:?}\n", key, val)?;
         }
         write!(f, "bdd size: {:?}\n", self.bdd_size)?;
+        writeln!(f, "approximation steps: {:?}", self.approximation_steps)?;
         Ok(())
     }
 }
@@ -0,0 +1,82 @@
+use crate::bdd::Bdd;
+use crate::expr::bool_expr::Expr;
+use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+impl BddVarOrdering {
+
+    /// Decide the satisfiability of the clauses by bucket elimination. Every clause
+    /// goes into the bucket of its variable on the lowest level. The buckets are
+    /// processed from the last level up: the Bdds of a bucket are conjoined and
+    /// the variable of the bucket is quantified away, and the result goes into the
+    /// bucket of its own lowest variable. As every variable is eliminated, the
+    /// result is the true Bdd if the clauses are satisfiable and the false one if not.
+    ///
+    /// No Bdd of the whole formula is built, so on instances of low treewidth
+    /// the intermediate Bdds stay much smaller than in `parallel_build`.
+    pub fn bucket_elimination(&self, clauses: &[Vec<i32>]) -> Bdd {
+        let levels = self.0[&i32::MAX];
+        let mut vars = vec![0; levels];
+        for (var, level) in self.0.iter().filter(|(var, _)| **var != i32::MAX) {
+            vars[*level] = *var;
+        }
+
+        let mut buckets: Vec<Vec<Bdd>> = vec![Vec::new(); levels];
+        for clause in clauses {
+            let bdd = self.build(&mut Expr::parse_clause(clause));
+            if !self.put_in_bucket(&mut buckets, bdd) {
+                return Bdd::new_false(&self.1);
+            }
+        }
+        for level in (0..levels).rev() {
+            let mut bucket = std::mem::take(&mut buckets[level]);
+            let Some(last) = bucket.pop() else { continue };
+            let mut conjunction = Bdd::new_true(&self.1);
+            for bdd in &bucket {
+                conjunction = self.and(&conjunction, bdd);
+            }
+            // the last conjunction and the quantification in one pass
+            let eliminated = self.and_exists(&conjunction, &last, &[vars[level]]);
+            if !self.put_in_bucket(&mut buckets, eliminated) {
+                return Bdd::new_false(&self.1);
+            }
+        }
+        Bdd::new_true(&self.1)
+    }
+
+    /// Put a Bdd into the bucket of its variable on the lowest level. The true Bdd
+    /// constrains nothing and is dropped. False if the Bdd is the false one.
+    fn put_in_bucket(&self, buckets: &mut [Vec<Bdd>], bdd: Bdd) -> bool {
+        if bdd.is_false() {
+            return false;
+        }
+        if let Some(level) = bdd.support().iter().map(|var| self.0[var]).max() {
+            buckets[level].push(bdd);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse::parse_dimacs;
+    use super::*;
+
+    #[test]
+    fn decide_satisfiability() {
+        for path in ["tests/test3.cnf", "tests/test4.cnf", "tests/test5.cnf"] {
+            let dimacs = parse_dimacs(path);
+            let var_ordering = BddVarOrdering::new(&dimacs);
+            assert!(var_ordering.bucket_elimination(&dimacs.clauses).is_true(), "{}", path);
+        }
+
+        // all the four clauses over x1 and x2
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let mut clauses = vec![vec![1, 2], vec![-1, 2], vec![1, -2], vec![-1, -2]];
+        assert!(var_ordering.bucket_elimination(&clauses).is_false());
+        // without one of them
+        clauses.pop();
+        clauses.push(vec![3, 4, -5]);
+        assert!(var_ordering.bucket_elimination(&clauses).is_true());
+    }
+}
@@ -0,0 +1,177 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use crate::bdd::Bdd;
+use crate::expr::bool_expr::Expr;
+use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+/// The order in which the Bdds of the clauses are conjoined by `build_clauses`
+/// and `parallel_build`. The order of the conjunctions decides how big the
+/// intermediate Bdds grow, while the file order of the clauses is mostly arbitrary.
+///
+/// `parallel_build` shares the learned clauses and approximates after every
+/// conjunction, so it conjoins the clauses one by one in the order of `arrange`
+/// and does not use `conjoin`.
+pub trait ClauseSchedule: Sync {
+
+    /// The order in which the clauses are conjoined one after the other.
+    /// By default the file order is kept.
+    fn arrange(&self, _ordering: &BddVarOrdering, clauses: &[Vec<i32>]) -> Vec<Vec<i32>> {
+        clauses.to_vec()
+    }
+
+    /// Conjoin the Bdds of the arranged clauses. By default from left to right.
+    fn conjoin(&self, ordering: &BddVarOrdering, bdds: Vec<Bdd>) -> Bdd {
+        let mut bdd = Bdd::new_true(ordering.manager());
+        for clause in &bdds {
+            bdd = ordering.and(&bdd, clause);
+        }
+        bdd
+    }
+}
+
+/// Get a clause schedule by its name: `file-order`, `top-level`,
+/// `bottom-level`, `balanced-tree` or `smallest-pair`.
+pub fn clause_schedule(name: &str) -> Option<Box<dyn ClauseSchedule>> {
+    match name {
+        "file-order" => Some(Box::new(FileOrder)),
+        "top-level" => Some(Box::new(TopLevel)),
+        "bottom-level" => Some(Box::new(BottomLevel)),
+        "balanced-tree" => Some(Box::new(BalancedTree)),
+        "smallest-pair" => Some(Box::new(SmallestPair)),
+        _ => None,
+    }
+}
+
+/// The clauses are conjoined in the order of the file.
+pub struct FileOrder;
+
+impl ClauseSchedule for FileOrder {}
+
+/// The clauses are sorted by the level of their top variable, so the Bdd
+/// grows from the top levels down.
+pub struct TopLevel;
+
+impl ClauseSchedule for TopLevel {
+    fn arrange(&self, ordering: &BddVarOrdering, clauses: &[Vec<i32>]) -> Vec<Vec<i32>> {
+        let mut arranged = clauses.to_vec();
+        arranged.sort_by_key(|clause| clause_levels(ordering, clause));
+        arranged
+    }
+}
+
+/// The clauses are sorted by the level of their bottom variable, the deepest
+/// first, so the Bdd grows from the bottom levels up.
+pub struct BottomLevel;
+
+impl ClauseSchedule for BottomLevel {
+    fn arrange(&self, ordering: &BddVarOrdering, clauses: &[Vec<i32>]) -> Vec<Vec<i32>> {
+        let mut arranged = clauses.to_vec();
+        arranged.sort_by_key(|clause| {
+            let (top, bottom) = clause_levels(ordering, clause);
+            (Reverse(bottom), Reverse(top))
+        });
+        arranged
+    }
+}
+
+/// The clauses are conjoined as a balanced binary tree: neighbouring clauses
+/// first, then neighbouring pairs and so on. The two halves of every
+/// subtree are built in parallel.
+pub struct BalancedTree;
+
+impl ClauseSchedule for BalancedTree {
+    fn conjoin(&self, ordering: &BddVarOrdering, bdds: Vec<Bdd>) -> Bdd {
+        fn conjoin_rec(ordering: &BddVarOrdering, bdds: &[Bdd]) -> Bdd {
+            match bdds.len() {
+                0 => Bdd::new_true(ordering.manager()),
+                1 => bdds[0].clone(),
+                len => {
+                    let (left, right) = bdds.split_at(len / 2);
+                    let (left, right) = rayon::join(|| conjoin_rec(ordering, left), || conjoin_rec(ordering, right));
+                    ordering.and(&left, &right)
+                }
+            }
+        }
+        conjoin_rec(ordering, &bdds)
+    }
+}
+
+/// Greedily conjoin the two smallest Bdds, until a single one is left. Small
+/// Bdds are cheap to conjoin and tend to stay small, like in a Huffman code.
+pub struct SmallestPair;
+
+impl ClauseSchedule for SmallestPair {
+    fn conjoin(&self, ordering: &BddVarOrdering, bdds: Vec<Bdd>) -> Bdd {
+        // the position breaks the ties, so the earlier Bdd is taken first
+        let mut heap: BinaryHeap<Reverse<(usize, usize)>> = BinaryHeap::new();
+        let mut pending: Vec<Option<Bdd>> = Vec::with_capacity(2 * bdds.len());
+        for bdd in bdds {
+            heap.push(Reverse((bdd.size(), pending.len())));
+            pending.push(Some(bdd));
+        }
+        while heap.len() > 1 {
+            let Reverse((_, first)) = heap.pop().unwrap();
+            let Reverse((_, second)) = heap.pop().unwrap();
+            let (left, right) = (pending[first].take().unwrap(), pending[second].take().unwrap());
+            let bdd = ordering.and(&left, &right);
+            if bdd.is_false() {
+                return bdd;
+            }
+            heap.push(Reverse((bdd.size(), pending.len())));
+            pending.push(Some(bdd));
+        }
+        match heap.pop() {
+            Some(Reverse((_, last))) => pending[last].take().unwrap(),
+            None => Bdd::new_true(ordering.manager()),
+        }
+    }
+}
+
+/// The levels of the top and of the bottom variable of a clause.
+fn clause_levels(ordering: &BddVarOrdering, clause: &[i32]) -> (usize, usize) {
+    let levels = clause.iter().map(|lit| ordering.0[&lit.abs()]);
+    (levels.clone().min().unwrap_or(0), levels.max().unwrap_or(0))
+}
+
+impl BddVarOrdering {
+
+    /// Build the Bdd of the conjunction of the clauses, following the schedule.
+    pub fn build_clauses(&self, clauses: &[Vec<i32>], schedule: &dyn ClauseSchedule) -> Bdd {
+        let bdds: Vec<Bdd> = schedule.arrange(self, clauses).iter()
+            .map(|clause| self.build(&mut Expr::parse_clause(clause)))
+            .collect();
+        schedule.conjoin(self, bdds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse::parse_dimacs;
+    use super::*;
+
+    #[test]
+    fn schedules_build_the_same_bdd() {
+        let dimacs = parse_dimacs("tests/test4.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let expected = var_ordering.build_clauses(&dimacs.clauses, &FileOrder);
+        for name in ["top-level", "bottom-level", "balanced-tree", "smallest-pair"] {
+            let bdd = var_ordering.build_clauses(&dimacs.clauses, clause_schedule(name).unwrap().as_ref());
+            assert_eq!(bdd, expected, "{}", name);
+        }
+        assert!(clause_schedule("random").is_none());
+        assert!(var_ordering.build_clauses(&[], &SmallestPair).is_true());
+    }
+
+    #[test]
+    fn clauses_sorted_by_level() {
+        // the levels are 4 2 5 1 3
+        let dimacs = parse_dimacs("tests/test5.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        assert_eq!(TopLevel.arrange(&var_ordering, &dimacs.clauses),
+                   vec![vec![-3, 4, 5], vec![1, -2], vec![2, 3], vec![-1, -5]]);
+        assert_eq!(BottomLevel.arrange(&var_ordering, &dimacs.clauses),
+                   vec![vec![2, 3], vec![-3, 4, 5], vec![-1, -5], vec![1, -2]]);
+    }
+}
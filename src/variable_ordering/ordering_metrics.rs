@@ -0,0 +1,90 @@
+use std::fmt;
+use crate::parser::parse::Dimacs;
+use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+/// Measures of how well an ordering fits the clauses of an instance, computed
+/// without building the Bdd. A clause spans the levels from its first to its
+/// last variable, and clauses with a short span can be processed locally.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderingMetrics {
+    /// The sum of the spans of the clauses.
+    pub total_span: usize,
+    /// The average span of a clause.
+    pub average_span: f64,
+    /// The largest number of clauses that cross the cut between two adjacent levels.
+    pub max_cut_width: usize,
+    /// The number of clauses spanning every level, from the top level down.
+    pub active_clauses: Vec<usize>,
+}
+
+impl OrderingMetrics {
+
+    /// Evaluate the ordering against the clauses of the instance.
+    ///
+    /// *Panics*:
+    ///  - If a variable of a clause is not in the ordering.
+    pub fn evaluate(ordering: &BddVarOrdering, dimacs: &Dimacs) -> OrderingMetrics {
+        let levels = ordering.0[&i32::MAX];
+        // the changes of the number of active and of cut clauses from one level to the next
+        let mut active_delta = vec![0i64; levels + 1];
+        let mut cut_delta = vec![0i64; levels + 1];
+        let mut total_span = 0;
+        let mut clauses = 0;
+        for clause in dimacs.clauses.iter().filter(|clause| !clause.is_empty()) {
+            let clause_levels = clause.iter().map(|lit| ordering.0[&lit.abs()]);
+            let first = clause_levels.clone().min().unwrap();
+            let last = clause_levels.max().unwrap();
+            total_span += last - first;
+            clauses += 1;
+            active_delta[first] += 1;
+            active_delta[last + 1] -= 1;
+            // the clause crosses the cuts below the levels first to last - 1
+            cut_delta[first] += 1;
+            cut_delta[last] -= 1;
+        }
+
+        let prefix_sums = |delta: &[i64]| -> Vec<usize> {
+            delta[..levels].iter()
+                .scan(0i64, |sum, change| { *sum += change; Some(*sum as usize) })
+                .collect()
+        };
+        let active_clauses = prefix_sums(&active_delta);
+        let max_cut_width = prefix_sums(&cut_delta).into_iter().max().unwrap_or(0);
+        OrderingMetrics {
+            total_span,
+            average_span: if clauses == 0 { 0.0 } else { total_span as f64 / clauses as f64 },
+            max_cut_width,
+            active_clauses,
+        }
+    }
+}
+
+impl fmt::Display for OrderingMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Total clause span: {}", self.total_span)?;
+        writeln!(f, "Average clause span: {:.2}", self.average_span)?;
+        writeln!(f, "Maximum cut-width: {}", self.max_cut_width)?;
+        write!(f, "Active clauses per level: {:?}", self.active_clauses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse::parse_dimacs;
+    use super::*;
+
+    #[test]
+    fn clause_spans_and_cuts() {
+        // the levels are 4 2 5 1 3
+        let dimacs = parse_dimacs("tests/test5.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let metrics = OrderingMetrics::evaluate(&var_ordering, &dimacs);
+
+        // the clauses span the levels 1..3, 1..4, 0..4 and 2..3
+        assert_eq!(metrics.total_span, 2 + 3 + 4 + 1);
+        assert_eq!(metrics.average_span, 2.5);
+        assert_eq!(metrics.active_clauses, vec![1, 3, 4, 4, 2]);
+        // the cut between the levels 2 and 3 is crossed by all the clauses
+        assert_eq!(metrics.max_cut_width, 4);
+    }
+}
@@ -0,0 +1,423 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use crate::parser::parse::Dimacs;
+use crate::variable_ordering::var_ordering_builder::BddVarOrderingBuilder;
+
+/// A static variable ordering heuristic, which places the variables of
+/// an instance on the levels of the Bdd before it is built.
+pub trait OrderingStrategy {
+    /// The variables of the instance, from the top level down.
+    fn order(&self, dimacs: &Dimacs) -> Vec<i32>;
+}
+
+/// Get an ordering strategy by its name, with its default parameters:
+/// `score`, `force`, `min-cut`, `cuthill-mckee` or `reverse-cuthill-mckee`.
+pub fn ordering_strategy(name: &str) -> Option<Box<dyn OrderingStrategy>> {
+    match name {
+        "score" => Some(Box::new(ScoreOrdering)),
+        "force" => Some(Box::new(Force::default())),
+        "min-cut" => Some(Box::new(MinCut::default())),
+        "cuthill-mckee" => Some(Box::new(CuthillMcKee { reverse: false })),
+        "reverse-cuthill-mckee" => Some(Box::new(CuthillMcKee { reverse: true })),
+        _ => None,
+    }
+}
+
+/// The clause score of `parse::calculate_score`: the variables appearing in
+/// many mostly short clauses come first.
+pub struct ScoreOrdering;
+
+impl OrderingStrategy for ScoreOrdering {
+    fn order(&self, dimacs: &Dimacs) -> Vec<i32> {
+        BddVarOrderingBuilder::order_by_score(&dimacs.vars_scores)
+    }
+}
+
+/// A given ordering, for example one loaded from a file or embedded in the
+/// header of the instance. The variables of the instance that are not in the
+/// ordering are put below it, by score.
+pub struct FixedOrdering(pub Vec<i32>);
+
+impl OrderingStrategy for FixedOrdering {
+    fn order(&self, dimacs: &Dimacs) -> Vec<i32> {
+        let mut order: Vec<i32> = Vec::with_capacity(dimacs.vars.len());
+        let mut placed: HashSet<i32> = HashSet::new();
+        for var in self.0.iter().chain(ScoreOrdering.order(dimacs).iter()) {
+            if placed.insert(*var) {
+                order.push(*var);
+            }
+        }
+        order
+    }
+}
+
+/// FORCE (Aloul, Markov and Sakallah): every clause pulls its variables towards
+/// its centre of gravity. Each iteration moves every variable to the average
+/// centre of the clauses it appears in, until the total clause span stops shrinking.
+/// The iterations start from the score ordering.
+pub struct Force {
+    pub iterations: usize,
+}
+
+impl Default for Force {
+    fn default() -> Self {
+        Force { iterations: 50 }
+    }
+}
+
+impl OrderingStrategy for Force {
+    fn order(&self, dimacs: &Dimacs) -> Vec<i32> {
+        let graph = Hypergraph::new(dimacs);
+        let mut order = ScoreOrdering.order(dimacs);
+        let mut best_span = graph.span(&order);
+        for _ in 0..self.iterations {
+            let positions = positions(&order);
+            let centres: Vec<f64> = graph.edges.iter()
+                .map(|edge| edge.iter().map(|var| positions[var] as f64).sum::<f64>() / edge.len() as f64)
+                .collect();
+            let mut moved: Vec<(f64, usize, i32)> = order.iter().enumerate()
+                .map(|(position, var)| {
+                    let edges = &graph.occurrences[var];
+                    let centre = edges.iter().map(|edge| centres[*edge]).sum::<f64>() / edges.len() as f64;
+                    (centre, position, *var)
+                })
+                .collect();
+            moved.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            let candidate: Vec<i32> = moved.into_iter().map(|(_, _, var)| var).collect();
+
+            let span = graph.span(&candidate);
+            if span >= best_span {
+                break;
+            }
+            best_span = span;
+            order = candidate;
+        }
+        order
+    }
+}
+
+/// MINCE-style recursive bisection: the variables are split into two halves
+/// which share as few clauses as possible, the first half goes above the
+/// second one and both are split again. Every split is improved by passes of
+/// Fiduccia-Mattheyses moves, starting from the halves of the score ordering.
+pub struct MinCut {
+    /// The maximum number of improving passes for every split.
+    pub passes: usize,
+}
+
+impl Default for MinCut {
+    fn default() -> Self {
+        MinCut { passes: 10 }
+    }
+}
+
+impl OrderingStrategy for MinCut {
+    fn order(&self, dimacs: &Dimacs) -> Vec<i32> {
+        let graph = Hypergraph::new(dimacs);
+        let mut order = Vec::with_capacity(dimacs.vars.len());
+        self.bisect(&graph, ScoreOrdering.order(dimacs), &mut order);
+        order
+    }
+}
+
+impl MinCut {
+
+    fn bisect(&self, graph: &Hypergraph, vars: Vec<i32>, order: &mut Vec<i32>) {
+        if vars.len() <= 2 {
+            order.extend(vars);
+            return;
+        }
+        let (upper, lower) = self.split(graph, vars);
+        self.bisect(graph, upper, order);
+        self.bisect(graph, lower, order);
+    }
+
+    /// Split the variables into two halves, keeping their relative order.
+    fn split(&self, graph: &Hypergraph, vars: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+        // the clauses restricted to the variables, the ones left with a
+        // single variable can never be cut
+        let included: HashSet<i32> = vars.iter().copied().collect();
+        let mut edges: Vec<Vec<i32>> = Vec::new();
+        let mut seen: HashSet<usize> = HashSet::new();
+        for var in &vars {
+            for edge in &graph.occurrences[var] {
+                if seen.insert(*edge) {
+                    let restricted: Vec<i32> = graph.edges[*edge].iter().copied().filter(|var| included.contains(var)).collect();
+                    if restricted.len() > 1 {
+                        edges.push(restricted);
+                    }
+                }
+            }
+        }
+        let mut occurrences: HashMap<i32, Vec<usize>> = vars.iter().map(|var| (*var, Vec::new())).collect();
+        for (idx, edge) in edges.iter().enumerate() {
+            for var in edge {
+                occurrences.get_mut(var).unwrap().push(idx);
+            }
+        }
+
+        let mut partition = Partition::new(&vars, edges, occurrences);
+        for _ in 0..self.passes {
+            if !partition.improve() {
+                break;
+            }
+        }
+        vars.into_iter().partition(|var| !partition.side[var])
+    }
+}
+
+/// A split of some variables into two sides, with the number of variables
+/// on each side for every clause.
+struct Partition {
+    edges: Vec<Vec<i32>>,
+    occurrences: HashMap<i32, Vec<usize>>,
+    // false for the upper side, true for the lower side
+    side: HashMap<i32, bool>,
+    counts: Vec<[usize; 2]>,
+    sizes: [usize; 2],
+    min_size: usize,
+}
+
+impl Partition {
+
+    fn new(vars: &[i32], edges: Vec<Vec<i32>>, occurrences: HashMap<i32, Vec<usize>>) -> Partition {
+        let side: HashMap<i32, bool> = vars.iter().enumerate().map(|(idx, var)| (*var, idx >= vars.len() / 2)).collect();
+        let counts = edges.iter()
+            .map(|edge| {
+                let lower = edge.iter().filter(|var| side[var]).count();
+                [edge.len() - lower, lower]
+            })
+            .collect();
+        // each side keeps at least 40 percent of the variables
+        let min_size = vars.len() * 2 / 5;
+        Partition { edges, occurrences, side, counts, sizes: [vars.len() / 2, vars.len() - vars.len() / 2], min_size }
+    }
+
+    /// The decrease of the number of cut clauses if `var` changes sides.
+    fn gain(&self, var: i32) -> i64 {
+        let from = self.side[&var] as usize;
+        self.occurrences[&var].iter()
+            .map(|edge| {
+                let count = self.counts[*edge];
+                if count[from] == 1 { 1 } else if count[1 - from] == 0 { -1 } else { 0 }
+            })
+            .sum()
+    }
+
+    fn flip(&mut self, var: i32) {
+        let from = self.side[&var] as usize;
+        for edge in &self.occurrences[&var] {
+            self.counts[*edge][from] -= 1;
+            self.counts[*edge][1 - from] += 1;
+        }
+        self.sizes[from] -= 1;
+        self.sizes[1 - from] += 1;
+        self.side.insert(var, from == 0);
+    }
+
+    /// One pass of Fiduccia-Mattheyses: every variable is moved once, always the
+    /// one with the best gain which keeps the sides balanced. Then the moves
+    /// after the best point of the pass are undone. True if the cut shrank.
+    fn improve(&mut self) -> bool {
+        let mut buckets: [BTreeSet<(Reverse<i64>, i32)>; 2] = [BTreeSet::new(), BTreeSet::new()];
+        let mut gains: HashMap<i32, i64> = HashMap::new();
+        for (var, side) in &self.side {
+            let gain = self.gain(*var);
+            gains.insert(*var, gain);
+            buckets[*side as usize].insert((Reverse(gain), *var));
+        }
+
+        let mut moves: Vec<i32> = Vec::new();
+        let (mut total, mut best_total, mut best_moves) = (0, 0, 0);
+        loop {
+            let candidates = (0..2)
+                .filter(|from| self.sizes[*from] > self.min_size)
+                .filter_map(|from| buckets[from].iter().next().copied());
+            let Some((Reverse(gain), var)) = candidates.min() else { break };
+            buckets[self.side[&var] as usize].remove(&(Reverse(gain), var));
+            gains.remove(&var);
+            self.flip(var);
+            moves.push(var);
+            total += gain;
+            if total > best_total {
+                best_total = total;
+                best_moves = moves.len();
+            }
+            // the gains of the unlocked neighbours change with the counts
+            for edge in self.occurrences[&var].clone() {
+                for neighbour in self.edges[edge].clone() {
+                    if let Some(old) = gains.get(&neighbour).copied() {
+                        let new = self.gain(neighbour);
+                        let side = self.side[&neighbour] as usize;
+                        buckets[side].remove(&(Reverse(old), neighbour));
+                        buckets[side].insert((Reverse(new), neighbour));
+                        gains.insert(neighbour, new);
+                    }
+                }
+            }
+        }
+        for var in moves.into_iter().skip(best_moves).rev() {
+            self.flip(var);
+        }
+        best_total > 0
+    }
+}
+
+/// Cuthill-McKee bandwidth reduction on the primal graph, in which two variables
+/// are adjacent if they share a clause. A breadth-first search starts from a
+/// variable of minimum degree and visits the neighbours by increasing degree,
+/// so adjacent variables end up on close levels. The reverse order is usually
+/// reported to be better.
+pub struct CuthillMcKee {
+    pub reverse: bool,
+}
+
+impl OrderingStrategy for CuthillMcKee {
+    fn order(&self, dimacs: &Dimacs) -> Vec<i32> {
+        let graph = Hypergraph::new(dimacs);
+        let mut neighbours: HashMap<i32, Vec<i32>> = HashMap::new();
+        for var in &graph.vars {
+            let mut adjacent: Vec<i32> = graph.occurrences[var].iter()
+                .flat_map(|edge| graph.edges[*edge].iter().copied())
+                .filter(|other| other != var)
+                .collect();
+            adjacent.sort();
+            adjacent.dedup();
+            neighbours.insert(*var, adjacent);
+        }
+        let by_degree = |vars: &mut Vec<i32>| vars.sort_by_key(|var| (neighbours[var].len(), *var));
+
+        let mut starts = graph.vars.clone();
+        by_degree(&mut starts);
+        let mut visited: HashSet<i32> = HashSet::new();
+        let mut order = Vec::with_capacity(starts.len());
+        // one search for every connected component
+        for start in starts {
+            if !visited.insert(start) {
+                continue;
+            }
+            let mut queue = VecDeque::from([start]);
+            while let Some(var) = queue.pop_front() {
+                order.push(var);
+                let mut next: Vec<i32> = neighbours[&var].iter().copied().filter(|other| visited.insert(*other)).collect();
+                by_degree(&mut next);
+                queue.extend(next);
+            }
+        }
+        if self.reverse {
+            order.reverse();
+        }
+        order
+    }
+}
+
+/// The clauses of an instance as hyperedges over its variables.
+struct Hypergraph {
+    vars: Vec<i32>,
+    edges: Vec<Vec<i32>>,
+    // the edges every variable appears in
+    occurrences: HashMap<i32, Vec<usize>>,
+}
+
+impl Hypergraph {
+
+    fn new(dimacs: &Dimacs) -> Hypergraph {
+        let mut occurrences: HashMap<i32, Vec<usize>> = dimacs.vars.iter().map(|var| (*var, Vec::new())).collect();
+        let mut edges = Vec::with_capacity(dimacs.clauses.len());
+        for (idx, clause) in dimacs.clauses.iter().enumerate() {
+            let mut edge: Vec<i32> = clause.iter().map(|lit| lit.abs()).collect();
+            edge.sort();
+            edge.dedup();
+            for var in &edge {
+                occurrences.get_mut(var).unwrap().push(idx);
+            }
+            edges.push(edge);
+        }
+        Hypergraph { vars: dimacs.vars.clone(), edges, occurrences }
+    }
+
+    /// The sum over the clauses of the distance between their first and last variable.
+    fn span(&self, order: &[i32]) -> usize {
+        let positions = positions(order);
+        self.edges.iter()
+            .filter(|edge| !edge.is_empty())
+            .map(|edge| {
+                let levels = edge.iter().map(|var| positions[var]);
+                levels.clone().max().unwrap() - levels.min().unwrap()
+            })
+            .sum()
+    }
+}
+
+fn positions(order: &[i32]) -> HashMap<i32, usize> {
+    order.iter().enumerate().map(|(position, var)| (*var, position)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse::parse_dimacs;
+    use crate::variable_ordering::var_ordering::BddVarOrdering;
+    use super::*;
+
+    fn dimacs_of(clauses: Vec<Vec<i32>>) -> Dimacs {
+        let mut vars: Vec<i32> = clauses.iter().flatten().map(|lit| lit.abs()).collect();
+        vars.sort();
+        vars.dedup();
+        Dimacs {
+            nb_v: vars.len(),
+            nb_c: clauses.len(),
+            vars_scores: vars.iter().map(|var| (*var, 1.0)).collect(),
+            vars,
+            clauses,
+            order: None,
+        }
+    }
+
+    #[test]
+    fn strategies_order_all_variables() {
+        let dimacs = parse_dimacs("tests/test4.cnf");
+        let mut expected = dimacs.vars.clone();
+        expected.sort();
+        for name in ["score", "force", "min-cut", "cuthill-mckee", "reverse-cuthill-mckee"] {
+            let mut order = ordering_strategy(name).unwrap().order(&dimacs);
+            order.sort();
+            assert_eq!(order, expected, "{}", name);
+        }
+        assert!(ordering_strategy("random").is_none());
+
+        let graph = Hypergraph::new(&dimacs);
+        assert!(graph.span(&Force::default().order(&dimacs)) <= graph.span(&ScoreOrdering.order(&dimacs)));
+    }
+
+    #[test]
+    fn chain_is_ordered_along_the_chain() {
+        // a chain 5 - 2 - 4 - 1 - 3 of binary clauses
+        let dimacs = dimacs_of(vec![vec![5, 2], vec![2, -4], vec![4, 1], vec![-1, 3]]);
+        let graph = Hypergraph::new(&dimacs);
+
+        assert_eq!(CuthillMcKee { reverse: false }.order(&dimacs), vec![3, 1, 4, 2, 5]);
+        assert_eq!(graph.span(&Force::default().order(&dimacs)), 4);
+        // the halves are cut once, but their levels are not ordered along the chain
+        assert!(graph.span(&MinCut::default().order(&dimacs)) < graph.span(&ScoreOrdering.order(&dimacs)));
+    }
+
+    #[test]
+    fn min_cut_separates_components() {
+        // two triangles which are interleaved in the score ordering
+        let dimacs = dimacs_of(vec![vec![1, 3], vec![3, 5], vec![1, 5], vec![2, 4], vec![4, 6], vec![2, 6]]);
+        let order = MinCut::default().order(&dimacs);
+        let (upper, _) = order.split_at(3);
+        assert!(upper.iter().all(|var| var % 2 == 0) || upper.iter().all(|var| var % 2 == 1));
+    }
+
+    #[test]
+    fn ordering_with_strategy() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::with_strategy(&dimacs, &CuthillMcKee { reverse: true });
+        let order = CuthillMcKee { reverse: true }.order(&dimacs);
+        for (level, var) in order.iter().enumerate() {
+            assert_eq!(var_ordering.0[var], level);
+        }
+        assert_eq!(var_ordering.0[&i32::MAX], order.len());
+    }
+}
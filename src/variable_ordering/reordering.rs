@@ -0,0 +1,258 @@
+use crate::bdd_manager::{BddManager, LiveNodeTable};
+use crate::bdd_util::BddVar;
+use crate::variable_ordering::var_ordering::BddVarOrdering;
+
+/// While a variable is sifted in one direction, it is moved back as soon
+/// as the diagram grows by more than this factor over the best size seen.
+const MAX_GROWTH: f64 = 1.2;
+
+/// The methods to dynamically reorder the variables of an ordering.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReorderingMethod {
+    /// Rudell's sifting: every variable is moved through all the levels
+    /// and put back on the level where the diagram was the smallest.
+    Sifting,
+    /// Try all the permutations of a window of 2 or 3 adjacent levels,
+    /// sliding the window from the top to the bottom.
+    WindowPermutation(usize),
+}
+
+impl BddVarOrdering {
+
+    /// Reorder the variables if reordering is enabled in the manager and
+    /// a `Bdd` of `size` nodes has grown past the threshold. Returns true
+    /// if the variables were reordered.
+    pub fn reorder_if_needed(&mut self, size: usize) -> bool {
+        let method = self.1.read().unwrap().reordering_due(size);
+        if let Some(method) = method {
+            self.reorder(method);
+        }
+        method.is_some()
+    }
+
+    /// Reorder the variables with the given method. The levels of the ordering
+    /// are updated in place and the nodes of all the live `Bdd`s of the manager
+    /// are rewritten for the new levels, so the `Bdd`s stay valid.
+    /// Clones of the ordering still have the old levels and must not be used
+    /// with the manager anymore.
+    pub fn reorder(&mut self, method: ReorderingMethod) {
+        let manager_ref = self.1.clone();
+        let mut manager = manager_ref.write().unwrap();
+        let mut sifting = Sifting::new(self, &mut manager);
+        match method {
+            ReorderingMethod::Sifting => sifting.sift(),
+            ReorderingMethod::WindowPermutation(size) => sifting.window_permutation(size),
+        }
+        let size = sifting.size;
+        manager.reordered(size);
+    }
+
+    /// Reorder the variables by sifting, see `ReorderingMethod::Sifting`.
+    pub fn sift(&mut self) {
+        self.reorder(ReorderingMethod::Sifting)
+    }
+
+    /// Reorder the variables by window permutation, see `ReorderingMethod::WindowPermutation`.
+    ///
+    /// *Panics*:
+    ///  - If the window does not have 2 or 3 levels.
+    pub fn window_permutation(&mut self, size: usize) {
+        self.reorder(ReorderingMethod::WindowPermutation(size))
+    }
+}
+
+/// The state of a reordering: the variable on every level, the live nodes
+/// and the number of live nodes under the current levels.
+struct Sifting<'a> {
+    ordering: &'a mut BddVarOrdering,
+    manager: &'a mut BddManager,
+    vars: Vec<i32>,
+    table: LiveNodeTable,
+    size: usize,
+}
+
+impl<'a> Sifting<'a> {
+
+    fn new(ordering: &'a mut BddVarOrdering, manager: &'a mut BddManager) -> Sifting<'a> {
+        let mut vars: Vec<(usize, i32)> = ordering.0.iter()
+            .filter(|(var, _)| **var != i32::MAX)
+            .map(|(var, level)| (*level, *var))
+            .collect();
+        vars.sort();
+        let table = LiveNodeTable::default();
+        let mut sifting = Sifting { ordering, manager, vars: vars.into_iter().map(|(_, var)| var).collect(), table, size: 0 };
+        sifting.compact();
+        sifting
+    }
+
+    /// Drop the dead nodes and restore the bottom-up order of the arena.
+    /// The nodes move, so the table of the live nodes is built again.
+    fn compact(&mut self) {
+        let levels = &self.ordering.0;
+        self.manager.collect_garbage_by_level(|var| levels[&var.0]);
+        self.table = self.manager.live_node_table();
+        self.size = self.table.count();
+    }
+
+    /// Swap the variables on the levels `level` and `level + 1`.
+    fn swap(&mut self, level: usize) {
+        let (upper, lower) = (self.vars[level], self.vars[level + 1]);
+        self.manager.swap_adjacent_vars(BddVar(upper), BddVar(lower), &mut self.table);
+        self.vars.swap(level, level + 1);
+        self.ordering.0.insert(upper, level + 1);
+        self.ordering.0.insert(lower, level);
+        self.size = self.table.count();
+    }
+
+    fn sift(&mut self) {
+        // the variables with the most nodes are sifted first
+        let mut vars = self.vars.clone();
+        vars.sort_by_key(|var| std::cmp::Reverse(self.table.count_of(BddVar(*var))));
+
+        for var in vars {
+            let start = self.ordering.0[&var];
+            let (mut best_level, mut best_size) = (start, self.size);
+            // move the variable to the nearer end first
+            let directions = if start < self.vars.len() / 2 { [false, true] } else { [true, false] };
+            for down in directions {
+                let mut level = self.ordering.0[&var];
+                loop {
+                    let at_end = if down { level + 1 == self.vars.len() } else { level == 0 };
+                    if at_end || self.size as f64 > best_size as f64 * MAX_GROWTH {
+                        break;
+                    }
+                    if down {
+                        self.swap(level);
+                        level += 1;
+                    } else {
+                        self.swap(level - 1);
+                        level -= 1;
+                    }
+                    if self.size < best_size {
+                        best_size = self.size;
+                        best_level = level;
+                    }
+                }
+            }
+            // move the variable back to the best level
+            let mut level = self.ordering.0[&var];
+            while level < best_level {
+                self.swap(level);
+                level += 1;
+            }
+            while level > best_level {
+                self.swap(level - 1);
+                level -= 1;
+            }
+            self.compact();
+        }
+    }
+
+    fn window_permutation(&mut self, size: usize) {
+        // the swaps of two adjacent levels that go through all the permutations of the window
+        let swaps: &[usize] = match size {
+            2 => &[0],
+            3 => &[0, 1, 0, 1, 0],
+            _ => panic!("A window has 2 or 3 levels, not {}.", size),
+        };
+        if self.vars.len() < size {
+            return;
+        }
+        for top in 0..=(self.vars.len() - size) {
+            let mut best = (self.size, self.vars[top..top + size].to_vec());
+            for swap in swaps {
+                self.swap(top + swap);
+                if self.size < best.0 {
+                    best = (self.size, self.vars[top..top + size].to_vec());
+                }
+            }
+            // bubble the variables of the window back into the best permutation
+            for (offset, var) in best.1.into_iter().enumerate() {
+                let mut level = self.ordering.0[&var];
+                while level > top + offset {
+                    self.swap(level - 1);
+                    level -= 1;
+                }
+            }
+            self.compact();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bdd::Bdd;
+    use crate::expr::bool_expr::Expr;
+    use crate::parser::parse::parse_dimacs;
+    use super::*;
+
+    /// $(x_1 \land x_4) \lor (x_2 \land x_5) \lor (x_3 \land x_6)$, which needs exponentially
+    /// more nodes when the variables of the pairs are far apart.
+    fn pairs(ordering: &BddVarOrdering) -> Bdd {
+        let mut bdd = Bdd::new_false(ordering.manager());
+        for (a, b) in [(1, 4), (2, 5), (3, 6)] {
+            let pair = ordering.and(&ordering.build(&mut Expr::Var(a)), &ordering.build(&mut Expr::Var(b)));
+            bdd = ordering.or(&bdd, &pair);
+        }
+        bdd
+    }
+
+    fn ordering_of(levels: &[i32]) -> BddVarOrdering {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let mut ordering = BddVarOrdering::new(&dimacs);
+        ordering.0.clear();
+        for (level, var) in levels.iter().enumerate() {
+            ordering.0.insert(*var, level);
+        }
+        ordering.0.insert(i32::MAX, levels.len());
+        ordering
+    }
+
+    #[test]
+    fn sifting_shrinks_the_diagram() {
+        let mut ordering = ordering_of(&[1, 2, 3, 4, 5, 6]);
+        let bdd = pairs(&ordering);
+        let (size, count) = (bdd.size(), bdd.sat_count(&ordering, 6));
+
+        ordering.sift();
+        assert!(bdd.size() < size);
+        assert_eq!(bdd.sat_count(&ordering, 6), count);
+        // the diagram is the one built from scratch under the new levels
+        assert_eq!(pairs(&ordering), bdd);
+        let mut levels: Vec<usize> = ordering.0.values().copied().collect();
+        levels.sort();
+        assert_eq!(levels, (0..7).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn swaps_count_the_live_nodes() {
+        let mut ordering = ordering_of(&[1, 2, 3, 4, 5, 6]);
+        let bdd = pairs(&ordering);
+        let negated = bdd.negate();
+        let manager_ref = ordering.manager().clone();
+        let mut manager = manager_ref.write().unwrap();
+        let mut sifting = Sifting::new(&mut ordering, &mut manager);
+
+        // move the first variable down to the last level and back up again
+        for level in (0..5).chain((0..5).rev()) {
+            sifting.swap(level);
+            assert_eq!(sifting.size, sifting.manager.live_node_table().count());
+        }
+        drop(manager);
+        assert_eq!(negated.negate(), bdd);
+        assert_eq!(pairs(&ordering), bdd);
+    }
+
+    #[test]
+    fn window_permutation_keeps_the_functions() {
+        let mut ordering = ordering_of(&[1, 2, 3, 4, 5, 6]);
+        let bdd = pairs(&ordering);
+        let negated = bdd.negate();
+        let size = bdd.size();
+
+        ordering.window_permutation(3);
+        assert!(bdd.size() <= size);
+        assert_eq!(negated.negate(), bdd);
+        assert_eq!(pairs(&ordering), bdd);
+    }
+}
@@ -1,29 +1,106 @@
+use std::sync::RwLockWriteGuard;
 use crossbeam_channel::{Receiver, TryRecvError};
 use crate::bdd::Bdd;
+use crate::bdd_manager::{BddManager, BddManagerRef, BddOp};
 use crate::bdd_util::*;
 use crate::expr::bool_expr;
 use crate::expr::bool_expr::Expr;
 use crate::expr::bool_expr::Expr::*;
 use crate::parser::parse::Dimacs;
+use crate::variable_ordering::clause_schedule::ClauseSchedule;
+use crate::variable_ordering::ordering_strategy::{FixedOrdering, OrderingStrategy};
 use crate::variable_ordering::var_ordering_builder::BddVarOrderingBuilder;
 use crate::statistics::stats::Stats;
 use crate::GlucoseWrapper;
 use crate::parallel::clause_database::ClauseDatabase;
 
+/// The mapping of each variable to its level in the Bdd, together with the
+/// manager that stores the nodes of all the `Bdd`s built for this ordering.
 #[derive(Clone, Debug)]
-pub struct BddVarOrdering(pub std::collections::HashMap<i32, usize>);
+pub struct BddVarOrdering(pub std::collections::HashMap<i32, usize>, pub BddManagerRef);
 
 impl BddVarOrdering {
 
-    /// Create a new `BddVarOrdering` with the given named variables.
+    /// Create a new `BddVarOrdering` with the given named variables. If the instance
+    /// embeds an ordering in `c order ...` comments, it is used for the levels.
     pub fn new(dimacs: &Dimacs) -> BddVarOrdering {
         let mut builder = BddVarOrderingBuilder::new();
         builder.make_variables(&dimacs.vars);
-        builder.make(&dimacs.vars_scores)
+        match &dimacs.order {
+            Some(order) => builder.make_with(&FixedOrdering(order.clone()), dimacs),
+            None => builder.make(&dimacs.vars_scores),
+        }
+    }
+
+    /// Load an ordering from a text file with one variable per line, from the top
+    /// level to the bottom level. Empty lines are skipped. The ordering only has
+    /// the variables of the file, see `load_for` to use it for an instance.
+    ///
+    /// A line that is not a positive variable or a variable that is listed twice
+    /// is an error of kind `InvalidData`.
+    pub fn load(path: &str) -> std::io::Result<BddVarOrdering> {
+        let order = BddVarOrdering::read_order(path)?;
+        let mut builder = BddVarOrderingBuilder::new();
+        builder.make_variables(&order);
+        Ok(builder.make_from_order(&order))
+    }
+
+    /// Load an ordering like `load` and complete it for the instance: the variables
+    /// of the instance that are not in the file are put below the loaded ones,
+    /// in the order of their scores.
+    pub fn load_for(path: &str, dimacs: &Dimacs) -> std::io::Result<BddVarOrdering> {
+        let order = BddVarOrdering::read_order(path)?;
+        let mut builder = BddVarOrderingBuilder::new();
+        builder.make_variables(&dimacs.vars);
+        Ok(builder.make_with(&FixedOrdering(order), dimacs))
+    }
+
+    fn read_order(path: &str) -> std::io::Result<Vec<i32>> {
+        let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        let mut order = Vec::new();
+        let mut listed = std::collections::HashSet::new();
+        for line in std::fs::read_to_string(path)?.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let var = line.parse::<i32>().ok().filter(|var| *var > 0 && *var != i32::MAX)
+                .ok_or_else(|| invalid(format!("Invalid variable in the ordering: {}", line)))?;
+            if !listed.insert(var) {
+                return Err(invalid(format!("The variable {} is listed twice in the ordering", var)));
+            }
+            order.push(var);
+        }
+        Ok(order)
+    }
+
+    /// Save the ordering in the format read by `load`, so that for example
+    /// the levels found by sifting can be used again.
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut levels: Vec<(usize, i32)> = self.0.iter()
+            .filter(|(var, _)| **var != i32::MAX)
+            .map(|(var, level)| (*level, *var))
+            .collect();
+        levels.sort();
+        let lines: Vec<String> = levels.into_iter().map(|(_, var)| format!("{}\n", var)).collect();
+        std::fs::write(path, lines.concat())
+    }
+
+    /// Create a new `BddVarOrdering` with the levels given by an ordering strategy.
+    pub fn with_strategy(dimacs: &Dimacs, strategy: &dyn OrderingStrategy) -> BddVarOrdering {
+        let mut builder = BddVarOrderingBuilder::new();
+        builder.make_variables(&dimacs.vars);
+        builder.make_with(strategy, dimacs)
+    }
+
+    /// The manager that stores the nodes of the `Bdd`s built for this ordering.
+    pub fn manager(&self) -> &BddManagerRef {
+        &self.1
     }
 
-    pub fn parallel_build(&self, vec_expr: &mut Vec<Expr>, clause_database: &mut ClauseDatabase, mut rec_depth: usize, solver_wrapper: GlucoseWrapper,
+    /// Build the Bdd of the clauses while Glucose runs, sharing the learned clauses
+    /// of the Bdd so far after every conjunction. The clauses are conjoined one
+    /// by one in the order of `schedule.arrange`, since the Bdd is approximated
+    /// and its clauses are sent between the conjunctions.
+    pub fn parallel_build(&mut self, clauses: &[Vec<i32>], schedule: &dyn ClauseSchedule, clause_database: &mut ClauseDatabase, mut rec_depth: usize, solver_wrapper: GlucoseWrapper,
                           stats: &mut Stats, receiver1: Receiver<()>,receiver2: Receiver<()>, receiver3: Receiver<()>) -> Bdd {
+        let mut vec_expr = Expr::parse_clauses(&schedule.arrange(self, clauses));
         // here we are investigating 2 new clauses
         rec_depth += 2;
         let mut current_bdd = self.build(&mut vec_expr[0]);
@@ -53,6 +130,12 @@ impl BddVarOrdering {
                 || self.build(&mut vec_expr[n]));
 
             current_bdd = self.and(&current_bdd, &temp_bdd);
+            // the size is counted once, and again only if reordering or approximation changed the diagram
+            let mut size = current_bdd.size();
+            // reorder the variables if the diagram has grown past the threshold of the manager
+            if self.reorder_if_needed(size) {
+                size = current_bdd.size();
+            }
             // these clauses need to be added to the clauses that the bdd will investigate/process
             //let clauses_to_add = current_bdd.receive_learned_clauses( clause_database, solver_wrapper, stats);
             //self.add_clauses_during_build(vec_expr, clauses_to_add);
@@ -67,10 +150,15 @@ impl BddVarOrdering {
                 Err(TryRecvError::Empty) => {}
             }
 
-            if rec_depth % 20 == 0 {
-                current_bdd.round_up(stats, receiver3.clone());
+            // over-approximate the diagram if it has grown past the node budget of the manager
+            let exact_bdd = current_bdd.clone();
+            if current_bdd.approximate_if_needed(size, self, stats, receiver3.clone()) {
+                size = current_bdd.size();
             }
-            stats.add_bdd_size(current_bdd.size());
+            // the learned clauses are read off the approximation, so in debug builds make sure it is sound
+            debug_assert!(self.verify_over_approximation(&exact_bdd, &current_bdd), "The approximation lost models of the Bdd");
+            debug_assert_eq!(current_bdd.check_invariants(self), Ok(()));
+            stats.add_bdd_size(size);
 
             // check if the other thread has finished
             match receiver1.try_recv() {
@@ -115,10 +203,10 @@ impl BddVarOrdering {
         // the exact same node exists in the node cache). The nodes for
         // each sub-expression are constructed by recursion.
         match expr {
-            Const(value) => Bdd::new_value(BddVar::new(i32::MAX), value),
+            Const(value) => Bdd::new_value(&self.1, value),
             Var(name) => {
                 let var = BddVar::new(*name);
-                Bdd::new_var(var)
+                Bdd::new_var(&self.1, var)
             },
             Not(inner) => self.build(inner).negate(),
             And(l, r) => {
@@ -135,34 +223,190 @@ impl BddVarOrdering {
     /// Create a `Bdd` corresponding to the $\phi \land \psi$ formula, where $\phi$ and $\psi$
     /// are the two given `Bdd`s.
     pub fn and(&self, left: &Bdd, right: &Bdd) -> Bdd {
-        self.apply(left, right, bool_expr::and)
+        self.apply(left, right, BddOp::And, bool_expr::and)
     }
 
     /// Create a `Bdd` corresponding to the $\phi \lor \psi$ formula, where $\phi$ and $\psi$
     /// are the two given `Bdd`s.
     pub fn or(&self, left: &Bdd, right: &Bdd) -> Bdd {
-        self.apply(left, right, bool_expr::or)
+        self.apply(left, right, BddOp::Or, bool_expr::or)
+    }
+
+    /// Create a `Bdd` corresponding to the $\phi \oplus \psi$ formula, where $\phi$ and $\psi$
+    /// are the two given `Bdd`s.
+    pub fn xor(&self, left: &Bdd, right: &Bdd) -> Bdd {
+        self.apply(left, right, BddOp::Xor, bool_expr::xor)
+    }
+
+    /// Create a `Bdd` corresponding to the $\phi \Rightarrow \psi$ formula, where $\phi$ and $\psi$
+    /// are the two given `Bdd`s.
+    pub fn imp(&self, left: &Bdd, right: &Bdd) -> Bdd {
+        self.apply(left, right, BddOp::Imp, bool_expr::imp)
+    }
+
+    /// Create a `Bdd` corresponding to the $\phi \Leftrightarrow \psi$ formula, where $\phi$ and $\psi$
+    /// are the two given `Bdd`s.
+    pub fn iff(&self, left: &Bdd, right: &Bdd) -> Bdd {
+        self.apply(left, right, BddOp::Iff, bool_expr::iff)
+    }
+
+    /// Create a `Bdd` corresponding to the $\neg(\phi \land \psi)$ formula, where $\phi$ and $\psi$
+    /// are the two given `Bdd`s.
+    pub fn nand(&self, left: &Bdd, right: &Bdd) -> Bdd {
+        self.apply(left, right, BddOp::Nand, bool_expr::nand)
+    }
+
+    /// Create a `Bdd` corresponding to the $\neg(\phi \lor \psi)$ formula, where $\phi$ and $\psi$
+    /// are the two given `Bdd`s.
+    pub fn nor(&self, left: &Bdd, right: &Bdd) -> Bdd {
+        self.apply(left, right, BddOp::Nor, bool_expr::nor)
+    }
+
+    /// Create a `Bdd` corresponding to the $\phi \land \neg \psi$ formula, where $\phi$ and $\psi$
+    /// are the two given `Bdd`s.
+    pub fn and_not(&self, left: &Bdd, right: &Bdd) -> Bdd {
+        self.apply(left, right, BddOp::AndNot, bool_expr::and_not)
+    }
+
+    /// Create a `Bdd` corresponding to if $\phi$ then $\psi$ else $\chi$, where $\phi$, $\psi$
+    /// and $\chi$ are the three given `Bdd`s. The triple is normalized to a standard triple
+    /// first, so that equivalent calls share one entry in the if-then-else table.
+    pub fn ite(&self, f: &Bdd, g: &Bdd, h: &Bdd) -> Bdd {
+        let mut manager = self.lock_for_operation();
+        let root = self.ite_rec(&mut manager, f.root_pointer(), g.root_pointer(), h.root_pointer());
+        Bdd::from_locked(&self.1, &manager, root)
+    }
+
+    /// Lock the manager for an operation on `Bdd`s of this ordering. Garbage is collected
+    /// here, if the arena has grown past the threshold, before the operation reads any
+    /// root pointer. So the operations of the build never use a pointer that was moved.
+    pub(crate) fn lock_for_operation(&self) -> RwLockWriteGuard<'_, BddManager> {
+        let mut manager = self.1.write().unwrap();
+        manager.collect_garbage_if_needed();
+        manager
+    }
+
+    /// The level of the variable of a pointer, the terminals are on the last level.
+    pub(crate) fn level(&self, manager: &BddManager, ptr: BddPointer) -> usize {
+        *self.0.get(&manager.var_of_ptr(ptr).0).unwrap()
+    }
+
+    /// True if `a` comes before `b` in the order (level, index) that is
+    /// used to pick one of two equivalent triples.
+    fn precedes(&self, manager: &BddManager, a: BddPointer, b: BddPointer) -> bool {
+        (self.level(manager, a), a.to_index()) < (self.level(manager, b), b.to_index())
+    }
+
+    pub(crate) fn ite_rec(&self, manager: &mut BddManager, mut f: BddPointer, mut g: BddPointer, mut h: BddPointer) -> BddPointer {
+        // terminal cases
+        if f.is_one() || g == h {
+            return g;
+        }
+        if f.is_zero() {
+            return h;
+        }
+        // replace the arguments that are equal to f or to its negation by constants
+        if g == f {
+            g = BddPointer::new_one();
+        } else if g == f.complement() {
+            g = BddPointer::new_zero();
+        }
+        if h == f {
+            h = BddPointer::new_zero();
+        } else if h == f.complement() {
+            h = BddPointer::new_one();
+        }
+        if g == h {
+            return g;
+        }
+        if g.is_one() && h.is_zero() {
+            return f;
+        }
+        if g.is_zero() && h.is_one() {
+            return f.complement();
+        }
+
+        // Standard triples: of two equivalent triples the one whose first
+        // argument comes first in the order is used.
+        if g.is_one() && self.precedes(manager, h, f) {
+            // ite(f,1,h) = ite(h,1,f)
+            std::mem::swap(&mut f, &mut h);
+        } else if h.is_zero() && self.precedes(manager, g, f) {
+            // ite(f,g,0) = ite(g,f,0)
+            std::mem::swap(&mut f, &mut g);
+        } else if g.is_zero() && self.precedes(manager, h, f) {
+            // ite(f,0,h) = ite(!h,0,!f)
+            let new_f = h.complement();
+            h = f.complement();
+            f = new_f;
+        } else if h.is_one() && self.precedes(manager, g, f) {
+            // ite(f,g,1) = ite(!g,!f,1)
+            let new_f = g.complement();
+            g = f.complement();
+            f = new_f;
+        } else if g == h.complement() && self.precedes(manager, g, f) {
+            // ite(f,g,!g) = ite(g,f,!f)
+            let new_f = g;
+            g = f;
+            h = f.complement();
+            f = new_f;
+        }
+        // The first argument is made regular, ite(!f,g,h) = ite(f,h,g), and so
+        // is the second one, ite(f,!g,!h) = !ite(f,g,h).
+        if f.is_complemented() {
+            f = f.complement();
+            std::mem::swap(&mut g, &mut h);
+        }
+        let negate = g.is_complemented() || g.is_zero();
+        if negate {
+            g = g.complement();
+            h = h.complement();
+        }
+
+        if let Some(result) = manager.cached_ite(f, g, h) {
+            return result.complement_if(negate);
+        }
+
+        // the top variable of the three and the cofactors with respect to it
+        let top = [f, g, h].into_iter()
+            .filter(|ptr| !ptr.is_terminal())
+            .min_by_key(|ptr| self.level(manager, *ptr))
+            .unwrap();
+        let top_var = manager.var_of_ptr(top);
+        let cofactors = |manager: &BddManager, ptr: BddPointer| {
+            if !ptr.is_terminal() && manager.var_of_ptr(ptr) == top_var {
+                (manager.low_node_ptr(ptr), manager.high_node_ptr(ptr))
+            } else {
+                (ptr, ptr)
+            }
+        };
+        let (f_low, f_high) = cofactors(manager, f);
+        let (g_low, g_high) = cofactors(manager, g);
+        let (h_low, h_high) = cofactors(manager, h);
+
+        let low = self.ite_rec(manager, f_low, g_low, h_low);
+        let high = self.ite_rec(manager, f_high, g_high, h_high);
+        let result = manager.mk_node(top_var, low, high);
+        manager.cache_ite_result(f, g, h, result);
+        result.complement_if(negate)
     }
 
-    fn apply<T>(&self, left: &Bdd, right: &Bdd, op: T) -> Bdd
+    /// The generic apply for the binary operation `op`. Its results are kept in the
+    /// computed table of the manager under `op_key`, so sub-results computed by
+    /// earlier calls are reused.
+    fn apply<T>(&self, left: &Bdd, right: &Bdd, op_key: BddOp, op: T) -> Bdd
         where
             T: Fn(Option<bool>, Option<bool>) -> Option<bool> {
 
-        let mut bdd = Bdd::new();
+        debug_assert!(std::sync::Arc::ptr_eq(left.manager(), &self.1)
+            && std::sync::Arc::ptr_eq(right.manager(), &self.1));
 
         // In order to ensure that the Obdd being constructed is reduced,
         // it is necessary to determine from a triple (i,l,h) whether there
         // exists a node u with var(u) = i, low(u) = l and high(u) = h.
-        // For this purpose we assume the presence of a table H:(i,l,h) -> u
-        // mapping triples (i,h,l) of variables indices i and nodes l and h to u.
-        // The table H is the "inverse" of the table T of variable nodes u.
-        // T(u) = (i,l,h) if and only if H(i,l,h) = u.
-
-        // We keep track of a nodes_map so that there are no duplicates
-        let mut nodes_map: std::collections::HashMap<BddNode, BddPointer> =
-            std::collections::HashMap::with_capacity(std::cmp::max(left.size(), right.size()));
-        nodes_map.insert(BddNode::mk_zero(BddVar::new(i32::MAX)), BddPointer::new_zero());
-        nodes_map.insert(BddNode::mk_one(BddVar::new(i32::MAX)), BddPointer::new_one());
+        // The manager keeps this table H:(i,l,h) -> u for all the Bdds
+        // of the ordering, so existing nodes are shared instead of duplicated.
+        let mut manager = self.lock_for_operation();
 
         // Task is a pair of pointers into the `left` and `right` BDDs.
         #[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
@@ -172,7 +416,7 @@ impl BddVarOrdering {
         }
 
         // We keep track of the tasks currently on stack so that we build the bdd from down to the top
-        let mut stack: Vec<Task> = Vec::with_capacity(std::cmp::max(left.size(), right.size()));
+        let mut stack: Vec<Task> = Vec::new();
 
         stack.push(Task {
             left: left.root_pointer(),
@@ -181,16 +425,20 @@ impl BddVarOrdering {
 
         // We keep track of the tasks already completed, so that we can access the pointers
         let mut finished_tasks: std::collections::HashMap<Task, BddPointer> =
-            std::collections::HashMap::with_capacity(std::cmp::max(left.size(), right.size()));
+            std::collections::HashMap::new();
 
         while let Some(current) = stack.last() {
 
             if finished_tasks.contains_key(current) {
                 stack.pop();
+            } else if let Some(result) = manager.cached(op_key, current.left, current.right) {
+                // computed by an earlier apply call
+                finished_tasks.insert(*current, result);
+                stack.pop();
             } else {
                 let (lft, rgt) = (current.left, current.right);
                 // find the lowest variable of the two nodes
-                let (l_var, r_var) = (left.var_of_ptr(lft), right.var_of_ptr(rgt));
+                let (l_var, r_var) = (manager.var_of_ptr(lft), manager.var_of_ptr(rgt));
 
                 //let min_var = min(l_var, r_var);
                 // The min variable is now the one with the higher score, so
@@ -204,12 +452,12 @@ impl BddVarOrdering {
                 // If they have different indices we proceed by pairing the node
                 // with lowest index with the low- and high- branches of the other.
                 let (l_low, l_high) = if l_var.eq(&min_var) {
-                    (left.low_node_ptr(lft), left.high_node_ptr(lft))
+                    (manager.low_node_ptr(lft), manager.high_node_ptr(lft))
                 } else {
                     (lft, lft)
                 };
                 let (r_low, r_high) = if l_var == r_var || r_var.eq(&min_var) {
-                    (right.low_node_ptr(rgt), right.high_node_ptr(rgt))
+                    (manager.low_node_ptr(rgt), manager.high_node_ptr(rgt))
                 } else {
                     (rgt, rgt)
                 };
@@ -233,20 +481,11 @@ impl BddVarOrdering {
                     .or(finished_tasks.get(&sub_right).cloned());
 
                 if let (Some(new_low), Some(new_high)) = (new_low, new_high) {
-                    if new_low == new_high {
-                        finished_tasks.insert(*current, new_low);
-                    } else {
-                        let node = BddNode::mk_node(min_var, new_low, new_high);
-                        if let Some(idx) = nodes_map.get(&node) {
-                            // Node already exists, just make it a result of this computation.
-                            finished_tasks.insert(*current, *idx);
-                        } else {
-                            // Node does not exist, it needs to be pushed to result.
-                            bdd.push_node(node);
-                            nodes_map.insert(node, bdd.root_pointer());
-                            finished_tasks.insert(*current, bdd.root_pointer());
-                        }
-                    }
+                    // The manager returns the existing node if there is one and
+                    // skips the node entirely if both branches are the same.
+                    let node = manager.mk_node(min_var, new_low, new_high);
+                    manager.cache_result(op_key, lft, rgt, node);
+                    finished_tasks.insert(*current, node);
                     // If both values are computed, mark this task as resolved.
                     stack.pop();
                 } else {
@@ -260,12 +499,14 @@ impl BddVarOrdering {
                 }
             }
         }
-        bdd
+        let root = finished_tasks[&Task { left: left.root_pointer(), right: right.root_pointer() }];
+        Bdd::from_locked(&self.1, &manager, root)
     }
 
 
     /*
-    pub fn parallel_build_and_send_assumptions(&self, vec_expr: &mut Vec<Expr>, clause_database: &mut ClauseDatabase, mut rec_depth: usize, solver_wrapper: GlucoseWrapper, stats: &mut Stats) -> Bdd {
+    pub fn parallel_build_and_send_assumptions(&self, clauses: &[Vec<i32>], schedule: &dyn ClauseSchedule, clause_database: &mut ClauseDatabase, mut rec_depth: usize, solver_wrapper: GlucoseWrapper, stats: &mut Stats) -> Bdd {
+        let vec_expr = Expr::parse_clauses(&schedule.arrange(self, clauses));
         // here we are investigating 2 new clauses
         rec_depth += 2;
         let mut current_bdd = self.build(&vec_expr[0]);
@@ -298,7 +539,8 @@ impl BddVarOrdering {
         current_bdd
     }
 
-    pub fn parallel_build_without_solver_just_for_testing(&self, vec_expr: &mut Vec<Expr>, clause_database: &mut ClauseDatabase, mut rec_depth: usize, stats: &mut Stats) -> Bdd {
+    pub fn parallel_build_without_solver_just_for_testing(&self, clauses: &[Vec<i32>], schedule: &dyn ClauseSchedule, clause_database: &mut ClauseDatabase, mut rec_depth: usize, stats: &mut Stats) -> Bdd {
+        let vec_expr = Expr::parse_clauses(&schedule.arrange(self, clauses));
         // here we are investigating 2 new clauses
         rec_depth += 2;
         let mut current_bdd = self.build(&vec_expr[0]);
@@ -333,4 +575,211 @@ impl BddVarOrdering {
         }
         current_bdd
     }*/
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expr::bool_expr::Expr;
+    use crate::parser::parse::parse_dimacs;
+    use super::*;
+
+    #[test]
+    fn build_shares_nodes() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let mut expr = Expr::parse_clause(&dimacs.clauses[0]);
+        let first = var_ordering.build(&mut expr);
+        let arena_size = var_ordering.manager().read().unwrap().size();
+        let second = var_ordering.build(&mut expr);
+
+        assert_eq!(first, second);
+        assert_eq!(arena_size, var_ordering.manager().read().unwrap().size());
+    }
+
+    #[test]
+    fn negation_shares_nodes() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let mut expr = Expr::parse_clause(&dimacs.clauses[3]);
+        let bdd = var_ordering.build(&mut expr);
+        let arena_size = var_ordering.manager().read().unwrap().size();
+        let negated = bdd.negate();
+
+        assert_eq!(arena_size, var_ordering.manager().read().unwrap().size());
+        assert_eq!(bdd.size(), negated.size());
+        assert_eq!(negated.negate(), bdd);
+        assert!(var_ordering.and(&bdd, &negated).is_false());
+        assert!(var_ordering.or(&bdd, &negated).is_true());
+    }
+
+    #[test]
+    fn boolean_operators() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let left = var_ordering.build(&mut Expr::parse_clause(&vec![1, -2]));
+        let right = var_ordering.build(&mut Expr::parse_clause(&vec![2, 3]));
+        let and = var_ordering.and(&left, &right);
+        let or = var_ordering.or(&left, &right);
+
+        assert_eq!(var_ordering.xor(&left, &right), var_ordering.and(&or, &and.negate()));
+        assert_eq!(var_ordering.iff(&left, &right), var_ordering.xor(&left, &right).negate());
+        assert_eq!(var_ordering.imp(&left, &right), var_ordering.or(&left.negate(), &right));
+        assert_eq!(var_ordering.nand(&left, &right), and.negate());
+        assert_eq!(var_ordering.nor(&left, &right), or.negate());
+        assert_eq!(var_ordering.and_not(&left, &right), var_ordering.and(&left, &right.negate()));
+        assert!(var_ordering.xor(&left, &left).is_false());
+    }
+
+    #[test]
+    fn if_then_else() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let manager = var_ordering.manager();
+
+        let f = var_ordering.build(&mut Expr::parse_clause(&vec![1, -2]));
+        let g = var_ordering.build(&mut Expr::parse_clause(&vec![2, 3]));
+        let h = var_ordering.build(&mut Expr::parse_clause(&vec![-3, 4]));
+        let (one, zero) = (Bdd::new_true(manager), Bdd::new_false(manager));
+
+        assert_eq!(var_ordering.ite(&f, &g, &zero), var_ordering.and(&f, &g));
+        assert_eq!(var_ordering.ite(&f, &one, &g), var_ordering.or(&f, &g));
+        assert_eq!(var_ordering.ite(&f, &g.negate(), &g), var_ordering.xor(&f, &g));
+        assert_eq!(var_ordering.ite(&f, &zero, &one), f.negate());
+        assert_eq!(var_ordering.ite(&f.negate(), &h, &g), var_ordering.ite(&f, &g, &h));
+
+        let mux = var_ordering.or(&var_ordering.and(&f, &g), &var_ordering.and(&f.negate(), &h));
+        assert_eq!(var_ordering.ite(&f, &g, &h), mux);
+    }
+
+    #[test]
+    fn build_and_solve() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let mut bdd = Bdd::new_true(var_ordering.manager());
+        for expr in Expr::parse_clauses(&dimacs.clauses).iter_mut() {
+            let clause_bdd = var_ordering.build(expr);
+            bdd = var_ordering.and(&bdd, &clause_bdd);
+        }
+        let mut assignment = bdd.solve(&dimacs.vars).unwrap();
+        for var in &dimacs.vars {
+            assignment.entry(*var).or_insert(false);
+        }
+        for clause in &dimacs.clauses {
+            assert_eq!(Expr::parse_clause(clause).set_vars_and_solve(&assignment), Some(true));
+        }
+    }
+
+    #[test]
+    fn collect_garbage_moves_roots() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+
+        let mut bdd = Bdd::new_true(var_ordering.manager());
+        for expr in Expr::parse_clauses(&dimacs.clauses).iter_mut() {
+            bdd = var_ordering.and(&bdd, &var_ordering.build(expr));
+        }
+        let negated = bdd.negate();
+        let (size, count) = (bdd.size(), bdd.sat_count(&var_ordering, 5));
+
+        // the clauses and the intermediate conjunctions are dropped
+        let collected = var_ordering.manager().write().unwrap().collect_garbage();
+        assert!(collected > 0);
+        assert_eq!(var_ordering.manager().read().unwrap().size(), size);
+        assert_eq!(bdd.size(), size);
+        assert_eq!(bdd.sat_count(&var_ordering, 5), count);
+        assert_eq!(negated.negate(), bdd);
+
+        // the unique table is rebuilt, so the same nodes are found again
+        let mut rebuilt = Bdd::new_true(var_ordering.manager());
+        for expr in Expr::parse_clauses(&dimacs.clauses).iter_mut() {
+            rebuilt = var_ordering.and(&rebuilt, &var_ordering.build(expr));
+        }
+        assert_eq!(rebuilt, bdd);
+    }
+
+    #[test]
+    fn collect_garbage_during_build() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let reference = BddVarOrdering::new(&dimacs);
+        var_ordering.manager().write().unwrap().set_gc_threshold(8);
+
+        let mut bdd = Bdd::new_true(var_ordering.manager());
+        let mut expected = Bdd::new_true(reference.manager());
+        for clause in &dimacs.clauses {
+            bdd = var_ordering.and(&bdd, &var_ordering.build(&mut Expr::parse_clause(clause)));
+            expected = reference.and(&expected, &reference.build(&mut Expr::parse_clause(clause)));
+            assert_eq!(bdd.size(), expected.size());
+        }
+        assert_eq!(bdd.sat_count(&var_ordering, 5), expected.sat_count(&reference, 5));
+        assert!(var_ordering.manager().read().unwrap().size() < reference.manager().read().unwrap().size());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dimacs = parse_dimacs("tests/test4.cnf");
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        let path = std::env::temp_dir().join(format!("bdd_ordering_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        var_ordering.save(path).unwrap();
+        let loaded = BddVarOrdering::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.0, var_ordering.0);
+
+        assert!(BddVarOrdering::load("tests/test4.cnf").is_err());
+    }
+
+    #[test]
+    fn load_invalid_orderings() {
+        let path = std::env::temp_dir().join(format!("bdd_invalid_ordering_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        for content in ["1\n2\n1\n", "1\n0\n", "2\n-1\n", "1\nx\n"] {
+            std::fs::write(path, content).unwrap();
+            let error = BddVarOrdering::load(path).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{:?}", content);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn load_for_an_instance() {
+        let dimacs = parse_dimacs("tests/test3.cnf");
+        let path = std::env::temp_dir().join(format!("bdd_partial_ordering_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "5\n3\n").unwrap();
+        let var_ordering = BddVarOrdering::load_for(path, &dimacs).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // the loaded variables on top, then the others by score
+        assert_eq!(var_ordering.0[&5], 0);
+        assert_eq!(var_ordering.0[&3], 1);
+        assert_eq!(var_ordering.0[&i32::MAX], 5);
+        // every variable of the instance has a level
+        let reference = BddVarOrdering::new(&dimacs);
+        let mut bdd = Bdd::new_true(var_ordering.manager());
+        let mut expected = Bdd::new_true(reference.manager());
+        for clause in &dimacs.clauses {
+            bdd = var_ordering.and(&bdd, &var_ordering.build(&mut Expr::parse_clause(clause)));
+            expected = reference.and(&expected, &reference.build(&mut Expr::parse_clause(clause)));
+        }
+        assert_eq!(bdd.sat_count(&var_ordering, 5), expected.sat_count(&reference, 5));
+    }
+
+    #[test]
+    fn embedded_ordering() {
+        let dimacs = parse_dimacs("tests/test5.cnf");
+        assert_eq!(dimacs.order, Some(vec![4, 2, 5, 1, 3]));
+        assert_eq!(dimacs.clauses.len(), 4);
+
+        let var_ordering = BddVarOrdering::new(&dimacs);
+        for (level, var) in [4, 2, 5, 1, 3].iter().enumerate() {
+            assert_eq!(var_ordering.0[var], level);
+        }
+        assert_eq!(parse_dimacs("tests/test3.cnf").order, None);
+    }
+}
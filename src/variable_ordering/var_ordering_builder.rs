@@ -1,6 +1,9 @@
 use std::cmp::Ordering;
 use std::cmp::Ordering::*;
+use crate::bdd_manager::BddManager;
 use crate::bdd_util::BddVar;
+use crate::parser::parse::Dimacs;
+use crate::variable_ordering::ordering_strategy::OrderingStrategy;
 use crate::variable_ordering::var_ordering::BddVarOrdering;
 
 #[derive(Clone, Debug)]
@@ -43,19 +46,43 @@ impl BddVarOrderingBuilder {
     /// so that higher-scoring variables
     /// (that is, variables that appear in many mostly short clauses)
     /// correspond to layers nearer the top of the BDD.
+    /// The ordering gets a fresh manager for the nodes of its `Bdd`s.
     pub fn make(self, vars_scores: &std::collections::HashMap<i32, f64>) -> BddVarOrdering {
+        self.make_from_order(&BddVarOrderingBuilder::order_by_score(vars_scores))
+    }
+
+    /// Convert this builder to a variable ordering with the levels given by `strategy`.
+    ///
+    /// *Panics*:
+    ///  - If the strategy leaves out a variable of the builder.
+    pub fn make_with(self, strategy: &dyn OrderingStrategy, dimacs: &Dimacs) -> BddVarOrdering {
+        let order = strategy.order(dimacs);
+        let ordered: std::collections::HashSet<i32> = order.iter().copied().collect();
+        if let Some(var) = self.var_names.iter().find(|var| !ordered.contains(var)) {
+            panic!("BDD variable {} is not in the ordering.", var);
+        }
+        self.make_from_order(&order)
+    }
+
+    /// Convert this builder to a variable ordering with the given levels, top to bottom.
+    pub(crate) fn make_from_order(self, order: &[i32]) -> BddVarOrdering {
         let mut mapping: std::collections::HashMap<i32, usize> = std::collections::HashMap::new();
-        let mut v: Vec<_> = vars_scores.into_iter().collect();
-        // v is a sorted vector in decreasing order according to the scores
-        v.sort_by(|x,y| BddVarOrderingBuilder::var_dec_cmp(&x.1, &y.1));
-        let mut idx = 0;
-        for (var, _) in v {
+        for (idx, var) in order.iter().enumerate() {
             mapping.insert(*var, idx);
-            idx += 1;
         }
-        mapping.insert(i32::MAX, idx);
+        mapping.insert(i32::MAX, order.len());
 
-        BddVarOrdering(mapping)
+        BddVarOrdering(mapping, BddManager::new_shared())
+    }
+
+    /// The variables sorted in decreasing order according to the score.
+    /// Variables with the same score are sorted by name, so the order is
+    /// the same on every run.
+    pub(crate) fn order_by_score(vars_scores: &std::collections::HashMap<i32, f64>) -> Vec<i32> {
+        let mut v: Vec<_> = vars_scores.iter().collect();
+        // v is a sorted vector in decreasing order according to the scores
+        v.sort_by(|x,y| BddVarOrderingBuilder::var_dec_cmp(x.1, y.1).then(x.0.cmp(y.0)));
+        v.into_iter().map(|(var, _)| *var).collect()
     }
 
     fn var_dec_cmp(x: &f64, y: &f64) -> Ordering {